@@ -1,7 +1,11 @@
 use std::{collections::HashMap, time::Duration};
 
 use async_trait::async_trait;
-use bg_coor::{core::TaskError, task_manager::TaskManager, worker::registry::TaskHandler};
+use bg_coor::{
+    core::{EnqueueOptions, TaskError},
+    task_manager::TaskManager,
+    worker::registry::TaskHandler,
+};
 use serde_json::{json, Value};
 use tokio::time::sleep;
 
@@ -15,7 +19,7 @@ impl TaskHandler for AddTask {
         _kwargs: HashMap<String, Value>,
     ) -> Result<Vec<u8>, TaskError> {
         // Parse arguments
-        let a = args.get(0).and_then(|v| v.as_i64()).ok_or_else(|| {
+        let a = args.first().and_then(|v| v.as_i64()).ok_or_else(|| {
             TaskError::InvalidArgument("First argument missing or invalid".into())
         })?;
 
@@ -51,27 +55,17 @@ async fn main() -> Result<(), TaskError> {
     };
 
     // Enqueue the task
-    let task_id = manager.enqueue_task(signature, 3).await?;
-    println!("Task ID: {}", task_id);
+    let options = EnqueueOptions::new().with_max_retries(3);
+    let handle = manager.enqueue_task_with_handle(signature, options).await?;
+    println!("Task ID: {}", handle.id());
 
-    loop {
-        let task_result = manager.get_task(task_id).await?;
-        match task_result {
-            Some(task) => {
-                if task.is_finished() {
-                    let string_result = task
-                        .get_result()
-                        .map(|r| String::from_utf8_lossy(r).to_string())
-                        .unwrap_or_else(|| "No result".to_string());
-                    println!("Task result: {}", string_result);
-                    break;
-                }
-            }
-            None => {
-                sleep(Duration::from_secs(1)).await;
-            }
-        }
-    }
+    // Wait for the task to finish
+    let task = handle.wait().await?;
+    let string_result = task
+        .get_result()
+        .map(|r| String::from_utf8_lossy(r).to_string())
+        .unwrap_or_else(|| "No result".to_string());
+    println!("Task result: {}", string_result);
 
     manager.shutdown().await
 }
@@ -1,7 +1,11 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use async_trait::async_trait;
-use bg_coor::{core::TaskError, task_manager::TaskManager, worker::registry::TaskHandler};
+use bg_coor::{
+    core::{EnqueueOptions, TaskError},
+    task_manager::TaskManager,
+    worker::registry::TaskHandler,
+};
 use serde_json::{json, Value};
 use yara::Compiler;
 
@@ -70,22 +74,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         kwargs: Default::default(),
     };
     
-    let task_id = task_manager.enqueue_task(signature, 3).await?;
+    let options = EnqueueOptions::new().with_max_retries(3);
+    let handle = task_manager
+        .enqueue_task_with_handle(signature, options)
+        .await?;
 
+    println!("Task ID: {}", handle.id());
 
-    println!("Task ID: {}", task_id);
-
-    loop {
-        let task = task_manager.get_task(task_id).await?;
-        if let Some(task) = task {
-            if task.is_finished() {
-                println!("Task finished");
-                let result = task.get_result().unwrap();
-                println!("Task result: {}", String::from_utf8(result.to_vec())?);
-                break;
-            }
-        }
-    }
+    let result = handle.result().await?;
+    println!("Task finished");
+    println!("Task result: {}", String::from_utf8(result)?);
 
     Ok(())
 }
\ No newline at end of file
@@ -1,23 +1,100 @@
 use async_trait::async_trait;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 use uuid::Uuid;
 
-use super::traits::Broker;
+use super::priority::queue_score;
+use super::traits::{Broker, DEFAULT_VISIBILITY_TIMEOUT};
 use crate::core::{Task, TaskError};
 
+// (score, sequence, id): lowest score first, FIFO among equal scores.
+type QueueEntry = Reverse<(i64, u64, Uuid)>;
+// (eta, sequence, id): earliest ETA first.
+type DelayedEntry = Reverse<(DateTime<Utc>, u64, Uuid)>;
+
+struct Queue {
+    heap: BinaryHeap<QueueEntry>,
+    delayed: BinaryHeap<DelayedEntry>,
+    sequence: u64,
+}
+
+impl Queue {
+    fn push(&mut self, task: &Task, aging: Option<Duration>) {
+        let now = Utc::now();
+        self.sequence += 1;
+        match task.eta() {
+            Some(eta) if eta > now => {
+                self.delayed.push(Reverse((eta, self.sequence, task.id())));
+            }
+            _ => {
+                let score = queue_score(task.priority(), now, aging);
+                self.heap.push(Reverse((score, self.sequence, task.id())));
+            }
+        }
+    }
+
+    /// Moves delayed tasks whose ETA has passed into the ready heap, scored
+    /// as if they were enqueued at their ETA.
+    fn promote_due(&mut self, tasks: &HashMap<Uuid, Task>, aging: Option<Duration>) {
+        let now = Utc::now();
+        while let Some(Reverse((eta, sequence, id))) = self.delayed.peek().copied() {
+            if eta > now {
+                break;
+            }
+            self.delayed.pop();
+            if let Some(task) = tasks.get(&id) {
+                let score = queue_score(task.priority(), eta, aging);
+                self.heap.push(Reverse((score, sequence, id)));
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<Uuid> {
+        self.heap.pop().map(|Reverse((_, _, id))| id)
+    }
+}
+
 pub struct MemoryBroker {
     tasks: Mutex<HashMap<Uuid, Task>>,
-    queue: Mutex<Vec<Uuid>>,
+    queue: Mutex<Queue>,
+    in_flight: Mutex<HashMap<Uuid, Instant>>,
+    // Unique key -> (holding task, expiry).
+    unique: Mutex<HashMap<String, (Uuid, Instant)>>,
+    visibility_timeout: Duration,
+    priority_aging: Option<Duration>,
 }
 
 impl MemoryBroker {
     pub fn new() -> Self {
         MemoryBroker {
             tasks: Mutex::new(HashMap::new()),
-            queue: Mutex::new(Vec::new()),
+            queue: Mutex::new(Queue {
+                heap: BinaryHeap::new(),
+                delayed: BinaryHeap::new(),
+                sequence: 0,
+            }),
+            in_flight: Mutex::new(HashMap::new()),
+            unique: Mutex::new(HashMap::new()),
+            visibility_timeout: DEFAULT_VISIBILITY_TIMEOUT,
+            priority_aging: None,
         }
     }
+
+    pub fn with_visibility_timeout(mut self, timeout: Duration) -> Self {
+        self.visibility_timeout = timeout;
+        self
+    }
+
+    /// Raises a waiting task's effective priority by one level per `aging`
+    /// interval so low priority tasks are not starved.
+    pub fn with_priority_aging(mut self, aging: Duration) -> Self {
+        self.priority_aging = Some(aging);
+        self
+    }
 }
 
 impl Default for MemoryBroker {
@@ -29,19 +106,33 @@ impl Default for MemoryBroker {
 #[async_trait]
 impl Broker for MemoryBroker {
     async fn push(&self, task: &Task) -> Result<(), TaskError> {
-        let id = task.id();
         let mut tasks = self.tasks.lock().await;
         let mut queue = self.queue.lock().await;
 
-        tasks.insert(id, task.clone());
-        queue.push(id);
+        tasks.insert(task.id(), task.clone());
+        queue.push(task, self.priority_aging);
         Ok(())
     }
 
     async fn pop(&self) -> Result<Option<Task>, TaskError> {
+        let tasks = self.tasks.lock().await;
         let mut queue = self.queue.lock().await;
+        let mut in_flight = self.in_flight.lock().await;
+
+        let now = Instant::now();
+        in_flight.retain(|id, deadline| {
+            if *deadline > now {
+                return true;
+            }
+            if let Some(task) = tasks.get(id) {
+                queue.push(task, self.priority_aging);
+            }
+            false
+        });
+        queue.promote_due(&tasks, self.priority_aging);
+
         if let Some(id) = queue.pop() {
-            let tasks = self.tasks.lock().await;
+            in_flight.insert(id, now + self.visibility_timeout);
             return Ok(tasks.get(&id).cloned());
         }
         Ok(None)
@@ -57,4 +148,75 @@ impl Broker for MemoryBroker {
         tasks.insert(task.id(), task.clone());
         Ok(())
     }
+
+    async fn remove(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut queue = self.queue.lock().await;
+
+        let queued = queue.heap.len() + queue.delayed.len();
+        queue.heap.retain(|Reverse((_, _, queued))| *queued != id);
+        queue
+            .delayed
+            .retain(|Reverse((_, _, queued))| *queued != id);
+        Ok(queue.heap.len() + queue.delayed.len() < queued)
+    }
+
+    async fn queue_length(&self) -> Result<usize, TaskError> {
+        let queue = self.queue.lock().await;
+        Ok(queue.heap.len() + queue.delayed.len())
+    }
+
+    async fn extend(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut in_flight = self.in_flight.lock().await;
+        Ok(match in_flight.get_mut(&id) {
+            Some(deadline) => {
+                *deadline = Instant::now() + self.visibility_timeout;
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn ack(&self, id: Uuid) -> Result<(), TaskError> {
+        let mut in_flight = self.in_flight.lock().await;
+        in_flight.remove(&id);
+        Ok(())
+    }
+
+    async fn nack(&self, id: Uuid, requeue: bool) -> Result<(), TaskError> {
+        let tasks = self.tasks.lock().await;
+        let mut queue = self.queue.lock().await;
+        let mut in_flight = self.in_flight.lock().await;
+
+        if in_flight.remove(&id).is_some() && requeue {
+            if let Some(task) = tasks.get(&id) {
+                queue.push(task, self.priority_aging);
+            }
+        }
+        Ok(())
+    }
+
+    async fn lock_unique(
+        &self,
+        key: &str,
+        id: Uuid,
+        window: Duration,
+    ) -> Result<Option<Uuid>, TaskError> {
+        let mut unique = self.unique.lock().await;
+        let now = Instant::now();
+        unique.retain(|_, (_, expires_at)| *expires_at > now);
+
+        if let Some((holder, _)) = unique.get(key) {
+            return Ok(Some(*holder));
+        }
+        unique.insert(key.to_string(), (id, now + window));
+        Ok(None)
+    }
+
+    async fn unlock_unique(&self, key: &str, id: Uuid) -> Result<(), TaskError> {
+        let mut unique = self.unique.lock().await;
+        if unique.get(key).is_some_and(|(holder, _)| *holder == id) {
+            unique.remove(key);
+        }
+        Ok(())
+    }
 }
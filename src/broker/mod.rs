@@ -1,3 +1,4 @@
 pub mod memory;
+mod priority;
 pub mod redis;
 pub mod traits;
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+// Without aging a single priority level outweighs ~139 years of waiting, so
+// ordering is strictly by priority and FIFO within a level.
+const STRICT_STEP_MS: i64 = 1 << 42;
+
+/// Queue ordering score for a task; lower scores are served first.
+///
+/// A task of priority `p` is treated as if it had been enqueued `p` aging
+/// steps earlier, which means a waiting task gains one priority level per
+/// `aging` interval. Because the offset is fixed at enqueue time the
+/// relative order never changes, so the score can be stored once in a heap
+/// or sorted set.
+pub(crate) fn queue_score(
+    priority: u8,
+    enqueued_at: DateTime<Utc>,
+    aging: Option<Duration>,
+) -> i64 {
+    enqueued_at.timestamp_millis() - i64::from(priority) * aging_step_ms(aging)
+}
+
+pub(crate) fn aging_step_ms(aging: Option<Duration>) -> i64 {
+    aging
+        .map(|aging| (aging.as_millis() as i64).max(1))
+        .unwrap_or(STRICT_STEP_MS)
+}
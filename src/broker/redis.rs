@@ -1,22 +1,110 @@
-use super::traits::Broker;
+use super::priority::{aging_step_ms, queue_score};
+use super::traits::{Broker, DEFAULT_VISIBILITY_TIMEOUT};
 use crate::core::{Task, TaskError};
 
 use async_trait::async_trait;
-use redis::{AsyncCommands, Client};
+use chrono::Utc;
+use redis::{AsyncCommands, Client, Script};
+use std::time::Duration;
 use uuid::Uuid;
 
+// Promotes delayed tasks whose ETA has passed and moves reservations whose
+// deadline has passed back onto the queue, then reserves the highest priority
+// task id until ARGV[2]. Promoted tasks are scored as if they were enqueued at
+// their ETA, requeued ones as if they were enqueued at ARGV[1].
+const RESERVE_SCRIPT: &str = r"
+local step = tonumber(ARGV[3])
+local function enqueue(id, enqueued_at)
+    local priority = tonumber(redis.call('HGET', KEYS[3], id) or '0')
+    local score = enqueued_at - priority * step
+    redis.call('ZADD', KEYS[1], string.format('%.0f', score), id)
+end
+
+local due = redis.call('ZRANGEBYSCORE', KEYS[4], '-inf', ARGV[1], 'WITHSCORES', 'LIMIT', 0, 1000)
+for i = 1, #due, 2 do
+    redis.call('ZREM', KEYS[4], due[i])
+    enqueue(due[i], tonumber(due[i + 1]))
+end
+
+local expired = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[1])
+for _, id in ipairs(expired) do
+    redis.call('ZREM', KEYS[2], id)
+    enqueue(id, tonumber(ARGV[1]))
+end
+
+local next = redis.call('ZPOPMIN', KEYS[1])
+if next[1] then
+    redis.call('ZADD', KEYS[2], ARGV[2], next[1])
+    return next[1]
+end
+return false
+";
+
+// Takes a unique key for ARGV[1] with SET NX, or returns the id holding it.
+const LOCK_UNIQUE_SCRIPT: &str = r"
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+    return false
+end
+return redis.call('GET', KEYS[1])
+";
+
+// Deletes a unique key only if ARGV[1] still holds it.
+const UNLOCK_UNIQUE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
 pub struct RedisBroker {
     client: Client,
     queue_key: String,
+    ready_key: String,
+    processing_key: String,
+    priorities_key: String,
+    delayed_key: String,
+    visibility_timeout: Duration,
+    priority_aging: Option<Duration>,
+    reserve_script: Script,
+    lock_unique_script: Script,
+    unlock_unique_script: Script,
 }
 
 impl RedisBroker {
+    /// Every key the broker uses starts with `queue_key`. Ready tasks wait
+    /// in the `{queue_key}:ready` sorted set; versions before priorities
+    /// kept a list under `queue_key` itself, which is no longer read.
     pub fn new(redis_url: &str, queue_key: &str) -> Result<Self, redis::RedisError> {
         Ok(Self {
             client: Client::open(redis_url)?,
             queue_key: queue_key.to_string(),
+            ready_key: format!("{}:ready", queue_key),
+            processing_key: format!("{}:processing", queue_key),
+            priorities_key: format!("{}:priorities", queue_key),
+            delayed_key: format!("{}:delayed", queue_key),
+            visibility_timeout: DEFAULT_VISIBILITY_TIMEOUT,
+            priority_aging: None,
+            reserve_script: Script::new(RESERVE_SCRIPT),
+            lock_unique_script: Script::new(LOCK_UNIQUE_SCRIPT),
+            unlock_unique_script: Script::new(UNLOCK_UNIQUE_SCRIPT),
         })
     }
+
+    pub fn with_visibility_timeout(mut self, timeout: Duration) -> Self {
+        self.visibility_timeout = timeout;
+        self
+    }
+
+    /// Raises a waiting task's effective priority by one level per `aging`
+    /// interval so low priority tasks are not starved.
+    pub fn with_priority_aging(mut self, aging: Duration) -> Self {
+        self.priority_aging = Some(aging);
+        self
+    }
+
+    fn unique_key(&self, key: &str) -> String {
+        format!("{}:unique:{}", self.queue_key, key)
+    }
 }
 
 #[async_trait]
@@ -25,21 +113,57 @@ impl Broker for RedisBroker {
         let mut conn = self.client.get_async_connection().await?;
         let task_json = serde_json::to_string(task)?;
 
-        let _: i64 = conn.lpush(&self.queue_key, &task_json).await?;
+        let id = task.id().to_string();
+        let now = Utc::now();
 
-        let _: () = conn.set(task.id().to_string(), task_json).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .set(&id, task_json)
+            .ignore()
+            .hset(&self.priorities_key, &id, task.priority())
+            .ignore();
+        match task.eta() {
+            // Delayed tasks wait in a sorted set keyed by ETA until `pop`
+            // promotes them onto the queue.
+            Some(eta) if eta > now => pipe.zadd(&self.delayed_key, &id, eta.timestamp_millis()),
+            _ => pipe.zadd(
+                &self.ready_key,
+                &id,
+                queue_score(task.priority(), now, self.priority_aging),
+            ),
+        };
+        let _: () = pipe.ignore().query_async(&mut conn).await?;
 
         Ok(())
     }
 
     async fn pop(&self) -> Result<Option<Task>, TaskError> {
         let mut conn = self.client.get_async_connection().await?;
-        let task_json: Option<String> = conn.rpop(&self.queue_key, None).await?;
+        let now = Utc::now().timestamp_millis();
+        let deadline = now + self.visibility_timeout.as_millis() as i64;
+
+        let id: Option<String> = self
+            .reserve_script
+            .key(&self.ready_key)
+            .key(&self.processing_key)
+            .key(&self.priorities_key)
+            .key(&self.delayed_key)
+            .arg(now)
+            .arg(deadline)
+            .arg(aging_step_ms(self.priority_aging))
+            .invoke_async(&mut conn)
+            .await?;
+
+        let Some(id) = id else {
+            return Ok(None);
+        };
 
+        let task_json: Option<String> = conn.get(&id).await?;
         if let Some(task_json) = task_json {
             let task: Task = serde_json::from_str(&task_json)?;
             Ok(Some(task))
         } else {
+            let _: i64 = conn.zrem(&self.processing_key, &id).await?;
             Ok(None)
         }
     }
@@ -63,4 +187,104 @@ impl Broker for RedisBroker {
         let _: String = conn.set(task.id().to_string(), task_json).await?;
         Ok(())
     }
+
+    async fn remove(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let (queued, delayed): (i64, i64) = redis::pipe()
+            .atomic()
+            .zrem(&self.ready_key, id.to_string())
+            .zrem(&self.delayed_key, id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        let removed = queued + delayed > 0;
+        if removed {
+            let _: i64 = conn.hdel(&self.priorities_key, id.to_string()).await?;
+        }
+        Ok(removed)
+    }
+
+    async fn queue_length(&self) -> Result<usize, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let (queued, delayed): (usize, usize) = redis::pipe()
+            .zcard(&self.ready_key)
+            .zcard(&self.delayed_key)
+            .query_async(&mut conn)
+            .await?;
+        Ok(queued + delayed)
+    }
+
+    async fn extend(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let deadline = Utc::now().timestamp_millis() + self.visibility_timeout.as_millis() as i64;
+        // XX leaves tasks that are no longer reserved alone.
+        let changed: i64 = redis::cmd("ZADD")
+            .arg(&self.processing_key)
+            .arg("XX")
+            .arg("CH")
+            .arg(deadline)
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(changed == 1)
+    }
+
+    async fn ack(&self, id: Uuid) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: () = redis::pipe()
+            .atomic()
+            .zrem(&self.processing_key, id.to_string())
+            .ignore()
+            .hdel(&self.priorities_key, id.to_string())
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn nack(&self, id: Uuid, requeue: bool) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let removed: i64 = conn.zrem(&self.processing_key, id.to_string()).await?;
+
+        // Only the caller that removed the reservation may requeue it.
+        if removed == 1 && requeue {
+            let priority: Option<u8> = conn.hget(&self.priorities_key, id.to_string()).await?;
+            let score = queue_score(priority.unwrap_or(0), Utc::now(), self.priority_aging);
+            let _: i64 = conn.zadd(&self.ready_key, id.to_string(), score).await?;
+        } else if removed == 1 {
+            let _: i64 = conn.hdel(&self.priorities_key, id.to_string()).await?;
+        }
+        Ok(())
+    }
+
+    async fn lock_unique(
+        &self,
+        key: &str,
+        id: Uuid,
+        window: Duration,
+    ) -> Result<Option<Uuid>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        // PX must be positive.
+        let window_ms = window.as_millis().max(1) as u64;
+        let holder: Option<String> = self
+            .lock_unique_script
+            .key(self.unique_key(key))
+            .arg(id.to_string())
+            .arg(window_ms)
+            .invoke_async(&mut conn)
+            .await?;
+        holder
+            .map(|holder| Uuid::parse_str(&holder).map_err(|e| TaskError::Other(e.to_string())))
+            .transpose()
+    }
+
+    async fn unlock_unique(&self, key: &str, id: Uuid) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: i64 = self
+            .unlock_unique_script
+            .key(self.unique_key(key))
+            .arg(id.to_string())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(())
+    }
 }
@@ -1,10 +1,40 @@
+use std::time::Duration;
+
 use crate::core::{Task, TaskError};
 use async_trait::async_trait;
 
+/// How long a popped task stays reserved before it is handed out again.
+pub const DEFAULT_VISIBILITY_TIMEOUT: Duration = Duration::from_secs(300);
+
 #[async_trait]
 pub trait Broker: Send + Sync {
     async fn push(&self, task: &Task) -> Result<(), TaskError>;
+    /// Reserves the next task for the broker's visibility timeout. The task is
+    /// redelivered unless it is `ack`ed or `nack`ed before the timeout expires.
     async fn pop(&self) -> Result<Option<Task>, TaskError>;
     async fn get_task(&self, id: uuid::Uuid) -> Result<Option<Task>, TaskError>;
     async fn update_task(&self, task: &Task) -> Result<(), TaskError>;
+    /// Drops a task that is still waiting to be delivered. Returns whether it
+    /// was queued; reserved tasks are left alone.
+    async fn remove(&self, id: uuid::Uuid) -> Result<bool, TaskError>;
+    /// Number of tasks waiting to be delivered, including delayed ones.
+    async fn queue_length(&self) -> Result<usize, TaskError>;
+    /// Pushes a reservation's deadline back to a full visibility timeout from
+    /// now, for tasks that are still running. Returns `false` if the task is
+    /// no longer reserved.
+    async fn extend(&self, id: uuid::Uuid) -> Result<bool, TaskError>;
+    /// Releases a reservation once the task has been fully processed.
+    async fn ack(&self, id: uuid::Uuid) -> Result<(), TaskError>;
+    /// Releases a reservation, putting the task back on the queue if `requeue` is set.
+    async fn nack(&self, id: uuid::Uuid, requeue: bool) -> Result<(), TaskError>;
+    /// Takes `key` for task `id` for `window`, atomically. Returns the id of
+    /// the task already holding it instead, if any.
+    async fn lock_unique(
+        &self,
+        key: &str,
+        id: uuid::Uuid,
+        window: Duration,
+    ) -> Result<Option<uuid::Uuid>, TaskError>;
+    /// Frees `key` if task `id` still holds it.
+    async fn unlock_unique(&self, key: &str, id: uuid::Uuid) -> Result<(), TaskError>;
 }
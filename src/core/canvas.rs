@@ -0,0 +1,158 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::TaskSignature;
+
+/// A task of a workflow that is enqueued later, under an id known up front.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    id: Uuid,
+    signature: TaskSignature,
+}
+
+impl Step {
+    pub fn new(signature: TaskSignature) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            signature,
+        }
+    }
+
+    /// Id of the task once it is enqueued.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn signature(&self) -> &TaskSignature {
+        &self.signature
+    }
+}
+
+/// Tasks run one after another. Each task gets the result of the previous
+/// one appended to its arguments; the chain stops at the first task that
+/// does not complete, and the tasks after it fail without running.
+#[derive(Debug, Clone, Default)]
+pub struct Chain {
+    pub(crate) signatures: Vec<TaskSignature>,
+}
+
+impl Chain {
+    pub fn new(signatures: impl IntoIterator<Item = TaskSignature>) -> Self {
+        Self {
+            signatures: signatures.into_iter().collect(),
+        }
+    }
+
+    pub fn then(mut self, signature: TaskSignature) -> Self {
+        self.signatures.push(signature);
+        self
+    }
+}
+
+/// Tasks run in parallel, tracked together under a group id.
+#[derive(Debug, Clone, Default)]
+pub struct Group {
+    pub(crate) signatures: Vec<TaskSignature>,
+}
+
+impl Group {
+    pub fn new(signatures: impl IntoIterator<Item = TaskSignature>) -> Self {
+        Self {
+            signatures: signatures.into_iter().collect(),
+        }
+    }
+
+    pub fn with(mut self, signature: TaskSignature) -> Self {
+        self.signatures.push(signature);
+        self
+    }
+}
+
+/// A group followed by a callback that gets the results of all members, in
+/// order, as its last argument. The callback only runs if every member
+/// completes; otherwise it fails without running.
+#[derive(Debug, Clone)]
+pub struct Chord {
+    pub(crate) group: Group,
+    pub(crate) callback: TaskSignature,
+}
+
+impl Chord {
+    pub fn new(group: Group, callback: TaskSignature) -> Self {
+        Self { group, callback }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupStatus {
+    #[default]
+    Running,
+    /// Every member completed.
+    Completed,
+    /// Every member finished, but not all of them completed.
+    Failed(String),
+}
+
+impl GroupStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, GroupStatus::Running)
+    }
+}
+
+/// A group as persisted in storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupRecord {
+    id: Uuid,
+    members: Vec<Uuid>,
+    callback: Option<Step>,
+    #[serde(default)]
+    status: GroupStatus,
+    created_at: DateTime<Utc>,
+}
+
+impl GroupRecord {
+    pub fn new(members: Vec<Uuid>, callback: Option<Step>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            members,
+            callback,
+            status: GroupStatus::Running,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// The children a suspended task waits for, under the task's own id.
+    pub(crate) fn children_of(parent_id: Uuid, children: Vec<Uuid>) -> Self {
+        Self {
+            id: parent_id,
+            ..Self::new(children, None)
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Ids of the member tasks, in the order they were added.
+    pub fn members(&self) -> &[Uuid] {
+        &self.members
+    }
+
+    /// The chord callback, if any.
+    pub fn callback(&self) -> Option<&Step> {
+        self.callback.as_ref()
+    }
+
+    pub fn status(&self) -> &GroupStatus {
+        &self.status
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub(crate) fn set_status(&mut self, status: GroupStatus) {
+        self.status = status;
+    }
+}
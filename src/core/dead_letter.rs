@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::Task;
+
+/// A copy of a task that failed for good, kept aside for inspection and
+/// replay. The task itself stays in storage as failed, so that its handles
+/// resolve, until the dead letter is purged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    task: Task,
+    reason: String,
+    failed_at: DateTime<Utc>,
+}
+
+impl DeadLetter {
+    pub fn new(task: Task, reason: impl Into<String>) -> Self {
+        Self {
+            task,
+            reason: reason.into(),
+            failed_at: Utc::now(),
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.task.id()
+    }
+
+    /// The task as it was when it failed.
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn failed_at(&self) -> DateTime<Utc> {
+        self.failed_at
+    }
+}
@@ -1,3 +1,6 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -32,9 +35,114 @@ pub enum TaskError {
     #[error("Invalid argument: {0}")]
     InvalidArgument(String),
 
+    #[error("Task {0} was cancelled")]
+    Cancelled(uuid::Uuid),
+
+    #[error("Task {0} exceeded its hard time limit")]
+    TimedOut(uuid::Uuid),
+
+    #[error("Timed out waiting for task {0}")]
+    WaitTimeout(uuid::Uuid),
+
+    /// Returned by a handler to be retried, after `after` or else the retry
+    /// policy's delay. Still bounded by the task's `max_retries`.
+    #[error("Retry requested")]
+    Retry { after: Option<Duration> },
+
+    /// Returned by a handler to fail the task without retrying it.
+    #[error("Task failed permanently: {0}")]
+    Fail(String),
+
+    /// Returned by a handler to drop the task without a result.
+    #[error("Task ignored")]
+    Ignore,
+
+    /// Returned by a handler after enqueueing children to free its worker
+    /// until they have finished. The handler then runs again and can read
+    /// their results from [`TaskContext::children`].
+    ///
+    /// [`TaskContext::children`]: crate::worker::context::TaskContext::children
+    #[error("Task suspended until its children finish")]
+    Suspend,
+
+    #[error("Task is a duplicate of {0}")]
+    Duplicate(uuid::Uuid),
+
     #[error("Other error: {0}")]
     Other(String),
 
     #[error("Redis error: {0}")]
     RedisError(#[from] redis::RedisError),
 }
+
+impl TaskError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            TaskError::ExecutionError(_) => ErrorKind::Execution,
+            TaskError::SerializationError(_) => ErrorKind::Serialization,
+            TaskError::NotFound(_) => ErrorKind::NotFound,
+            TaskError::MaxRetriesExceeded => ErrorKind::MaxRetriesExceeded,
+            TaskError::ValidationError(_) => ErrorKind::Validation,
+            TaskError::HandlerNotFound(_) => ErrorKind::HandlerNotFound,
+            TaskError::InvalidSignature => ErrorKind::InvalidSignature,
+            TaskError::RegistryLocked(_) => ErrorKind::RegistryLocked,
+            TaskError::ShutdownError(_) => ErrorKind::Shutdown,
+            TaskError::InvalidArgument(_) => ErrorKind::InvalidArgument,
+            TaskError::Cancelled(_) => ErrorKind::Cancelled,
+            TaskError::TimedOut(_) => ErrorKind::TimedOut,
+            TaskError::WaitTimeout(_) => ErrorKind::WaitTimeout,
+            TaskError::Retry { .. } => ErrorKind::Retry,
+            TaskError::Fail(_) => ErrorKind::Fail,
+            TaskError::Ignore => ErrorKind::Ignore,
+            TaskError::Suspend => ErrorKind::Suspend,
+            TaskError::Duplicate(_) => ErrorKind::Duplicate,
+            TaskError::Other(_) => ErrorKind::Other,
+            TaskError::RedisError(_) => ErrorKind::Redis,
+        }
+    }
+}
+
+/// The variant of a [`TaskError`], without its details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorKind {
+    Execution,
+    Serialization,
+    NotFound,
+    MaxRetriesExceeded,
+    Validation,
+    HandlerNotFound,
+    InvalidSignature,
+    RegistryLocked,
+    Shutdown,
+    InvalidArgument,
+    Cancelled,
+    TimedOut,
+    WaitTimeout,
+    Retry,
+    Fail,
+    Ignore,
+    Suspend,
+    Duplicate,
+    Other,
+    Redis,
+}
+
+impl ErrorKind {
+    /// Whether errors of this kind are retried unless a retry policy says
+    /// otherwise. Errors caused by the task itself would fail the same way
+    /// on every attempt.
+    pub fn is_transient(self) -> bool {
+        !matches!(
+            self,
+            ErrorKind::Serialization
+                | ErrorKind::Validation
+                | ErrorKind::HandlerNotFound
+                | ErrorKind::InvalidSignature
+                | ErrorKind::InvalidArgument
+                | ErrorKind::Fail
+                | ErrorKind::Ignore
+                | ErrorKind::Suspend
+                | ErrorKind::Duplicate
+        )
+    }
+}
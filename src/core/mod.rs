@@ -1,5 +1,23 @@
+mod canvas;
+mod dead_letter;
 mod error;
+mod options;
+mod progress;
+mod retry;
+mod saga;
 mod task;
+mod unique;
+mod workflow;
 
-pub use error::TaskError;
-pub use task::{Task, TaskSignature, TaskStatus};
+pub use canvas::{Chain, Chord, Group, GroupRecord, GroupStatus, Step};
+pub use dead_letter::DeadLetter;
+pub use error::{ErrorKind, TaskError};
+pub use options::EnqueueOptions;
+pub use progress::TaskProgress;
+pub use retry::{Backoff, Jitter, RetryPolicy, MAX_RETRY_DELAY};
+pub use saga::{Saga, SagaPosition, SagaRecord, SagaStatus, SagaStep, SagaTransition};
+pub use task::{Task, TaskSignature, TaskStatus, TaskTree};
+pub use unique::{OnDuplicate, Unique, DEFAULT_UNIQUE_WINDOW};
+pub use workflow::{
+    Edge, EdgeCondition, NodeState, Workflow, WorkflowNode, WorkflowRecord, WorkflowStatus,
+};
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{RetryPolicy, TaskError, Unique};
+
+/// Per-task settings applied when a task is enqueued.
+#[derive(Debug, Clone, Default)]
+pub struct EnqueueOptions {
+    pub(crate) max_retries: u32,
+    pub(crate) priority: u8,
+    pub(crate) eta: Option<DateTime<Utc>>,
+    pub(crate) countdown: Option<Duration>,
+    pub(crate) soft_time_limit: Option<Duration>,
+    pub(crate) hard_time_limit: Option<Duration>,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) headers: HashMap<String, Value>,
+    pub(crate) unique: Option<Unique>,
+    pub(crate) parent_id: Option<Uuid>,
+    pub(crate) root_id: Option<Uuid>,
+}
+
+impl EnqueueOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Higher priorities are served first. Defaults to `0`.
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Runs the task no earlier than `eta`.
+    pub fn with_eta(mut self, eta: DateTime<Utc>) -> Self {
+        self.eta = Some(eta);
+        self.countdown = None;
+        self
+    }
+
+    /// Runs the task no earlier than `countdown` after it is enqueued.
+    pub fn with_countdown(mut self, countdown: Duration) -> Self {
+        self.countdown = Some(countdown);
+        self.eta = None;
+        self
+    }
+
+    /// Notifies the handler once the task has run for `limit`. Overrides the
+    /// handler's default.
+    pub fn with_soft_time_limit(mut self, limit: Duration) -> Self {
+        self.soft_time_limit = Some(limit);
+        self
+    }
+
+    /// Aborts the task once it has run for `limit`. Overrides the handler's
+    /// default.
+    pub fn with_hard_time_limit(mut self, limit: Duration) -> Self {
+        self.hard_time_limit = Some(limit);
+        self
+    }
+
+    /// Overrides the handler's retry policy.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Attaches metadata such as a correlation id or tenant to the task. It
+    /// is kept across retries and passed on to the task's children.
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_headers<K, V>(mut self, headers: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.headers
+            .extend(headers.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Enqueues the task only if no duplicate of it was enqueued within
+    /// the window of `unique`. Applies to tasks enqueued one at a time, not
+    /// to chains, groups and other workflows.
+    pub fn with_unique(mut self, unique: Unique) -> Self {
+        self.unique = Some(unique);
+        self
+    }
+
+    pub(crate) fn resolve_eta(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, TaskError> {
+        match self.countdown {
+            Some(countdown) => chrono::Duration::from_std(countdown)
+                .ok()
+                .and_then(|countdown| now.checked_add_signed(countdown))
+                .map(Some)
+                .ok_or_else(|| TaskError::InvalidArgument("Countdown is too long".to_string())),
+            None => Ok(self.eta),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How far a running task has got, as last reported by its handler.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskProgress {
+    current: Option<u64>,
+    total: Option<u64>,
+    percentage: Option<f64>,
+    meta: Option<Value>,
+    updated_at: DateTime<Utc>,
+}
+
+impl TaskProgress {
+    /// `current` out of `total` units of work done.
+    pub fn new(current: u64, total: u64) -> Self {
+        Self {
+            current: Some(current),
+            total: Some(total),
+            ..Self::empty()
+        }
+    }
+
+    /// `percentage` of the work done, from `0.0` to `100.0`.
+    pub fn from_percentage(percentage: f64) -> Self {
+        Self {
+            percentage: Some(percentage.clamp(0.0, 100.0)),
+            ..Self::empty()
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            current: None,
+            total: None,
+            percentage: None,
+            meta: None,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Attaches free-form state, e.g. the item currently being processed.
+    pub fn with_meta(mut self, meta: Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    pub fn current(&self) -> Option<u64> {
+        self.current
+    }
+
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// The reported percentage, or else the share of `current` in `total`.
+    pub fn percentage(&self) -> Option<f64> {
+        self.percentage.or(match (self.current, self.total) {
+            (Some(current), Some(total)) if total > 0 => {
+                Some((current as f64 / total as f64 * 100.0).min(100.0))
+            }
+            _ => None,
+        })
+    }
+
+    pub fn meta(&self) -> Option<&Value> {
+        self.meta.as_ref()
+    }
+
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{ErrorKind, TaskError};
+
+/// Longest a task is held back before a retry; longer delays are cut short.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// How the delay grows between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backoff {
+    Fixed(Duration),
+    /// `initial`, then `increment` longer for every further attempt.
+    Linear {
+        initial: Duration,
+        increment: Duration,
+    },
+    /// `base`, doubling for every further attempt.
+    Exponential(Duration),
+}
+
+/// Randomisation applied to the backoff so that tasks failing together do
+/// not retry together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Jitter {
+    #[default]
+    None,
+    /// Anywhere between zero and the backoff delay.
+    Full,
+    /// Between the first delay and three times the previous one, ignoring
+    /// the backoff's growth.
+    Decorrelated,
+}
+
+/// Whether and when a failed task is attempted again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    jitter: Jitter,
+    max_delay: Option<Duration>,
+    #[serde(default)]
+    retryable: Option<Vec<ErrorKind>>,
+}
+
+impl RetryPolicy {
+    pub fn fixed(delay: Duration) -> Self {
+        Self::new(Backoff::Fixed(delay))
+    }
+
+    pub fn linear(initial: Duration, increment: Duration) -> Self {
+        Self::new(Backoff::Linear { initial, increment })
+    }
+
+    /// Doubles the delay from `base` on every attempt, up to `max`.
+    pub fn exponential(base: Duration, max: Duration) -> Self {
+        Self::new(Backoff::Exponential(base)).with_max_delay(max)
+    }
+
+    /// Retries right away.
+    pub fn immediate() -> Self {
+        Self::fixed(Duration::ZERO)
+    }
+
+    fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            jitter: Jitter::None,
+            max_delay: None,
+            retryable: None,
+        }
+    }
+
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Caps every delay, jitter included.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Retries only errors of these kinds. By default every transient error
+    /// is retried, see [`ErrorKind::is_transient`].
+    pub fn with_retryable(mut self, kinds: impl IntoIterator<Item = ErrorKind>) -> Self {
+        self.retryable = Some(kinds.into_iter().collect());
+        self
+    }
+
+    /// Whether a task that failed with `error` may be retried. Handlers can
+    /// always ask for a retry with [`TaskError::Retry`], and never get one
+    /// after [`TaskError::Fail`] or [`TaskError::Ignore`].
+    pub fn is_retryable(&self, error: &TaskError) -> bool {
+        match error.kind() {
+            ErrorKind::Retry => true,
+            ErrorKind::Fail | ErrorKind::Ignore => false,
+            kind => match &self.retryable {
+                Some(retryable) => retryable.contains(&kind),
+                None => kind.is_transient(),
+            },
+        }
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    pub fn jitter(&self) -> Jitter {
+        self.jitter
+    }
+
+    pub fn max_delay(&self) -> Option<Duration> {
+        self.max_delay
+    }
+
+    /// Delay before retry number `attempt`, starting at `1`. `previous` is
+    /// the delay used before the last retry, if any.
+    pub fn delay(&self, attempt: u32, previous: Option<Duration>) -> Duration {
+        let attempt = attempt.max(1);
+        let delay = match self.jitter {
+            Jitter::None => self.backoff_delay(attempt),
+            Jitter::Full => random_between(Duration::ZERO, self.backoff_delay(attempt)),
+            Jitter::Decorrelated => {
+                let first = self.backoff_delay(1);
+                let upper = previous.unwrap_or(first).saturating_mul(3);
+                random_between(first, upper.max(first))
+            }
+        };
+        match self.max_delay {
+            Some(max_delay) => delay.min(max_delay),
+            None => delay,
+        }
+    }
+
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Linear { initial, increment } => {
+                initial.saturating_add(increment.saturating_mul(attempt - 1))
+            }
+            Backoff::Exponential(base) => base.saturating_mul(2u32.saturating_pow(attempt - 1)),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::immediate()
+    }
+}
+
+fn random_between(low: Duration, high: Duration) -> Duration {
+    let millis = rand::thread_rng().gen_range(low.as_millis()..=high.as_millis());
+    Duration::from_millis(millis.min(u64::MAX as u128) as u64)
+}
@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{Step, TaskSignature};
+
+/// Steps run one after another, each paired with a compensation that undoes
+/// it. If a step does not complete, the compensations of the steps before it
+/// run one after another in reverse order.
+#[derive(Debug, Clone, Default)]
+pub struct Saga {
+    pub(crate) steps: Vec<(TaskSignature, Option<TaskSignature>)>,
+}
+
+impl Saga {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(mut self, action: TaskSignature, compensation: TaskSignature) -> Self {
+        self.steps.push((action, Some(compensation)));
+        self
+    }
+
+    /// Adds a step that has nothing to undo.
+    pub fn step_without_compensation(mut self, action: TaskSignature) -> Self {
+        self.steps.push((action, None));
+        self
+    }
+}
+
+/// Which task of a saga a task runs, by step index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SagaPosition {
+    Action(usize),
+    Compensation(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SagaStatus {
+    Running,
+    Completed,
+    /// A step did not complete; the steps before it are being undone.
+    Compensating,
+    Compensated,
+    /// A compensation did not complete, leaving the remaining steps as they
+    /// are.
+    CompensationFailed,
+}
+
+impl SagaStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, SagaStatus::Running | SagaStatus::Compensating)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SagaStep {
+    action: Step,
+    compensation: Option<Step>,
+}
+
+impl SagaStep {
+    pub fn action(&self) -> &Step {
+        &self.action
+    }
+
+    pub fn compensation(&self) -> Option<&Step> {
+        self.compensation.as_ref()
+    }
+}
+
+/// A change of a saga's status, caused by the task of step `step`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SagaTransition {
+    status: SagaStatus,
+    step: usize,
+    at: DateTime<Utc>,
+}
+
+impl SagaTransition {
+    pub fn status(&self) -> SagaStatus {
+        self.status
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at
+    }
+}
+
+/// A saga as persisted in storage, with the history of its status.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SagaRecord {
+    id: Uuid,
+    steps: Vec<SagaStep>,
+    transitions: Vec<SagaTransition>,
+    created_at: DateTime<Utc>,
+}
+
+impl SagaRecord {
+    pub fn new(saga: Saga) -> Self {
+        let steps = saga
+            .steps
+            .into_iter()
+            .map(|(action, compensation)| SagaStep {
+                action: Step::new(action),
+                compensation: compensation.map(Step::new),
+            })
+            .collect();
+        let mut record = Self {
+            id: Uuid::new_v4(),
+            steps,
+            transitions: Vec::new(),
+            created_at: Utc::now(),
+        };
+        record.transition(SagaStatus::Running, 0);
+        record
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn steps(&self) -> &[SagaStep] {
+        &self.steps
+    }
+
+    pub fn status(&self) -> SagaStatus {
+        self.transitions
+            .last()
+            .map_or(SagaStatus::Running, SagaTransition::status)
+    }
+
+    /// Every status the saga went through, oldest first.
+    pub fn transitions(&self) -> &[SagaTransition] {
+        &self.transitions
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    /// The latest step before `step` that has a compensation, with it.
+    pub(crate) fn compensation_before(&self, step: usize) -> Option<(usize, &Step)> {
+        self.steps[..step]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, step)| Some((index, step.compensation()?)))
+    }
+
+    pub(crate) fn transition(&mut self, status: SagaStatus, step: usize) {
+        self.transitions.push(SagaTransition {
+            status,
+            step,
+            at: Utc::now(),
+        });
+    }
+}
@@ -1,11 +1,14 @@
 use core::fmt;
 use std::collections::HashMap;
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
+use super::{RetryPolicy, SagaPosition, Step, TaskError, TaskProgress, MAX_RETRY_DELAY};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
     pub(crate) id: Uuid,
@@ -16,15 +19,61 @@ pub struct Task {
     pub(crate) retries: u32,
     pub(crate) max_retries: u32,
     pub(crate) result: Option<Vec<u8>>,
+    #[serde(default)]
+    pub(crate) heartbeat_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) priority: u8,
+    #[serde(default)]
+    pub(crate) eta: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) soft_time_limit: Option<Duration>,
+    #[serde(default)]
+    pub(crate) hard_time_limit: Option<Duration>,
+    #[serde(default)]
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    #[serde(default)]
+    pub(crate) retry_delay: Option<Duration>,
+    #[serde(default)]
+    pub(crate) next_retry_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) headers: HashMap<String, Value>,
+    #[serde(default)]
+    pub(crate) progress: Option<TaskProgress>,
+    /// Remaining steps of the chain this task belongs to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) chain: Vec<Step>,
+    #[serde(default)]
+    pub(crate) group_id: Option<Uuid>,
+    #[serde(default)]
+    pub(crate) workflow_id: Option<Uuid>,
+    #[serde(default)]
+    pub(crate) workflow_node: Option<String>,
+    #[serde(default)]
+    pub(crate) saga_id: Option<Uuid>,
+    #[serde(default)]
+    pub(crate) saga_position: Option<SagaPosition>,
+    /// Task whose handler enqueued this one.
+    #[serde(default)]
+    pub(crate) parent_id: Option<Uuid>,
+    /// Top of the tree of tasks this one was enqueued in.
+    #[serde(default)]
+    pub(crate) root_id: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum TaskStatus {
     Pending,
+    Scheduled,
     Running,
     Completed,
     Cancelled,
     Failed(String),
+    /// Aborted for running past its hard time limit.
+    TimedOut,
+    /// Dropped by its handler without a result.
+    Ignored,
+    /// Suspended by its handler until its children have finished.
+    Waiting,
 }
 
 impl Task {
@@ -38,6 +87,24 @@ impl Task {
             retries: 0,
             max_retries,
             result: None,
+            heartbeat_at: None,
+            priority: 0,
+            eta: None,
+            soft_time_limit: None,
+            hard_time_limit: None,
+            retry_policy: None,
+            retry_delay: None,
+            next_retry_at: None,
+            headers: HashMap::new(),
+            progress: None,
+            chain: Vec::new(),
+            group_id: None,
+            workflow_id: None,
+            workflow_node: None,
+            saga_id: None,
+            saga_position: None,
+            parent_id: None,
+            root_id: None,
         }
     }
 
@@ -57,6 +124,21 @@ impl Task {
         &self.payload
     }
 
+    pub fn set_payload(&mut self, payload: Vec<u8>) {
+        self.payload = payload;
+    }
+
+    /// Decodes the signature the task was created from.
+    pub fn signature(&self) -> Result<TaskSignature, TaskError> {
+        Ok(serde_json::from_slice(&self.payload)?)
+    }
+
+    /// Replaces the task's name and payload with `signature`.
+    pub fn set_signature(&mut self, signature: &TaskSignature) {
+        self.name = signature.name.clone();
+        self.payload = signature.to_bytes();
+    }
+
     pub fn retries(&self) -> u32 {
         self.retries
     }
@@ -65,10 +147,115 @@ impl Task {
         self.max_retries
     }
 
+    /// Higher priorities are served first.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    pub fn set_priority(&mut self, priority: u8) {
+        self.priority = priority;
+    }
+
+    /// Earliest time the task may be handed to a worker.
+    pub fn eta(&self) -> Option<DateTime<Utc>> {
+        self.eta
+    }
+
+    pub fn set_eta(&mut self, eta: Option<DateTime<Utc>>) {
+        self.eta = eta;
+    }
+
+    /// Whether the task must still wait for its ETA at `now`.
+    pub fn is_delayed(&self, now: DateTime<Utc>) -> bool {
+        self.eta.is_some_and(|eta| eta > now)
+    }
+
+    /// How long the task may run before its handler is asked to wrap up.
+    pub fn soft_time_limit(&self) -> Option<Duration> {
+        self.soft_time_limit
+    }
+
+    pub fn set_soft_time_limit(&mut self, limit: Option<Duration>) {
+        self.soft_time_limit = limit;
+    }
+
+    /// How long the task may run before it is aborted.
+    pub fn hard_time_limit(&self) -> Option<Duration> {
+        self.hard_time_limit
+    }
+
+    pub fn set_hard_time_limit(&mut self, limit: Option<Duration>) {
+        self.hard_time_limit = limit;
+    }
+
+    /// Overrides the handler's retry policy for this task.
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
+    }
+
+    /// Delay before the latest retry.
+    pub fn retry_delay(&self) -> Option<Duration> {
+        self.retry_delay
+    }
+
+    /// When the latest retry becomes due.
+    pub fn next_retry_at(&self) -> Option<DateTime<Utc>> {
+        self.next_retry_at
+    }
+
+    /// Holds the task back for `delay`, at most [`MAX_RETRY_DELAY`], before
+    /// it is attempted again.
+    pub fn schedule_retry(&mut self, delay: Duration) {
+        let delay = delay.min(MAX_RETRY_DELAY);
+        let next_retry_at = chrono::Duration::from_std(delay)
+            .ok()
+            .and_then(|delay| Utc::now().checked_add_signed(delay))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        self.retry_delay = Some(delay);
+        self.next_retry_at = Some(next_retry_at);
+        self.eta = Some(next_retry_at);
+    }
+
+    pub fn headers(&self) -> &HashMap<String, Value> {
+        &self.headers
+    }
+
+    pub fn set_header(&mut self, key: impl Into<String>, value: impl Into<Value>) {
+        self.headers.insert(key.into(), value.into());
+    }
+
+    /// Latest progress reported by the task's handler.
+    pub fn progress(&self) -> Option<&TaskProgress> {
+        self.progress.as_ref()
+    }
+
+    pub fn set_progress(&mut self, progress: Option<TaskProgress>) {
+        self.progress = progress;
+    }
+
     pub fn created_at(&self) -> DateTime<Utc> {
         self.created_at
     }
 
+    pub fn heartbeat_at(&self) -> Option<DateTime<Utc>> {
+        self.heartbeat_at
+    }
+
+    /// Records that a worker is still alive and processing this task.
+    pub fn heartbeat(&mut self) {
+        self.heartbeat_at = Some(Utc::now());
+    }
+
+    /// Whether the task is running but was last heard from before
+    /// `stale_before`.
+    pub(crate) fn is_stale(&self, stale_before: DateTime<Utc>) -> bool {
+        self.is_running() && self.heartbeat_at.unwrap_or(self.created_at) < stale_before
+    }
+
     pub fn set_status(&mut self, status: TaskStatus) {
         self.status = status;
     }
@@ -84,7 +271,11 @@ impl Task {
     pub fn is_finished(&self) -> bool {
         matches!(
             self.status,
-            TaskStatus::Completed | TaskStatus::Cancelled | TaskStatus::Failed(_)
+            TaskStatus::Completed
+                | TaskStatus::Cancelled
+                | TaskStatus::Failed(_)
+                | TaskStatus::TimedOut
+                | TaskStatus::Ignored
         )
     }
 
@@ -96,6 +287,51 @@ impl Task {
         self.result.as_deref()
     }
 
+    /// The result as JSON, as passed on to the next tasks of a workflow.
+    /// Results that are not JSON are passed as a string.
+    pub fn result_value(&self) -> Value {
+        match &self.result {
+            Some(result) => serde_json::from_slice(result)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(result).into_owned())),
+            None => Value::Null,
+        }
+    }
+
+    /// Steps of the chain that run after this task.
+    pub fn chain(&self) -> &[Step] {
+        &self.chain
+    }
+
+    pub fn group_id(&self) -> Option<Uuid> {
+        self.group_id
+    }
+
+    pub fn workflow_id(&self) -> Option<Uuid> {
+        self.workflow_id
+    }
+
+    /// Key of the node this task runs in its workflow.
+    pub fn workflow_node(&self) -> Option<&str> {
+        self.workflow_node.as_deref()
+    }
+
+    pub fn saga_id(&self) -> Option<Uuid> {
+        self.saga_id
+    }
+
+    /// Which action or compensation of its saga this task runs.
+    pub fn saga_position(&self) -> Option<SagaPosition> {
+        self.saga_position
+    }
+
+    pub fn parent_id(&self) -> Option<Uuid> {
+        self.parent_id
+    }
+
+    pub fn root_id(&self) -> Option<Uuid> {
+        self.root_id
+    }
+
     pub fn set_result(&mut self, result: Vec<u8>) {
         self.result = Some(result);
     }
@@ -107,7 +343,36 @@ impl fmt::Display for Task {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// A task with the tasks its handler enqueued, and theirs in turn.
+#[derive(Debug, Clone)]
+pub struct TaskTree {
+    task: Task,
+    children: Vec<TaskTree>,
+}
+
+impl TaskTree {
+    /// Builds the tree under `task` from the children of each task, by
+    /// parent id.
+    pub(crate) fn assemble(task: Task, children: &mut HashMap<Uuid, Vec<Task>>) -> Self {
+        let children = children
+            .remove(&task.id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|child| Self::assemble(child, children))
+            .collect();
+        Self { task, children }
+    }
+
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    pub fn children(&self) -> &[TaskTree] {
+        &self.children
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TaskSignature {
     pub name: String,
     pub args: Vec<Value>,
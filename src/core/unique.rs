@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use super::TaskSignature;
+
+/// How long a unique key is held unless set otherwise.
+pub const DEFAULT_UNIQUE_WINDOW: Duration = Duration::from_secs(3600);
+
+/// What enqueueing a duplicate of a task still holding its key does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// Returns the id of the task holding the key.
+    #[default]
+    ReturnExisting,
+    /// Fails with [`super::TaskError::Duplicate`].
+    Reject,
+}
+
+/// Keeps a task from being enqueued twice within a window. The first task
+/// enqueued under a key holds it for the window, whatever becomes of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unique {
+    key: Option<String>,
+    window: Duration,
+    on_duplicate: OnDuplicate,
+}
+
+impl Unique {
+    /// Tasks are duplicates if they have the same name, arguments and
+    /// keyword arguments.
+    pub fn by_signature() -> Self {
+        Self {
+            key: None,
+            window: DEFAULT_UNIQUE_WINDOW,
+            on_duplicate: OnDuplicate::default(),
+        }
+    }
+
+    /// Tasks are duplicates if they have the same idempotency key.
+    pub fn by_key(key: impl Into<String>) -> Self {
+        Self {
+            key: Some(key.into()),
+            ..Self::by_signature()
+        }
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_on_duplicate(mut self, on_duplicate: OnDuplicate) -> Self {
+        self.on_duplicate = on_duplicate;
+        self
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn on_duplicate(&self) -> OnDuplicate {
+        self.on_duplicate
+    }
+
+    /// The key a task of `signature` is locked under.
+    pub fn key_for(&self, signature: &TaskSignature) -> String {
+        if let Some(key) = &self.key {
+            return key.clone();
+        }
+        // Keyword arguments are sorted so that their order does not matter.
+        let kwargs: BTreeMap<_, _> = signature.kwargs.iter().collect();
+        let canonical = serde_json::to_vec(&(&signature.name, &signature.args, kwargs))
+            .expect("signatures serialize to JSON");
+        format!("{}:{:x}", signature.name, Sha256::digest(canonical))
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{TaskError, TaskSignature};
+
+/// When an edge lets its target run, depending on how its source ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EdgeCondition {
+    OnSuccess,
+    OnFailure,
+    /// Whether the source completed or failed, but not if it was skipped.
+    Always,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edge {
+    from: String,
+    to: String,
+    condition: EdgeCondition,
+}
+
+impl Edge {
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+
+    pub fn condition(&self) -> EdgeCondition {
+        self.condition
+    }
+
+    /// Whether the target may run given the state of the source, or `None`
+    /// while the source has not finished.
+    fn is_satisfied(&self, source: NodeState) -> Option<bool> {
+        match (source, self.condition) {
+            (NodeState::Pending | NodeState::Enqueued, _) => None,
+            (NodeState::Completed, EdgeCondition::OnSuccess | EdgeCondition::Always) => Some(true),
+            (NodeState::Failed, EdgeCondition::OnFailure | EdgeCondition::Always) => Some(true),
+            _ => Some(false),
+        }
+    }
+}
+
+/// A graph of tasks. A node runs once all of its dependencies have finished
+/// and every incoming edge's condition holds; otherwise it is skipped, and
+/// so are the nodes depending on it.
+#[derive(Debug, Clone, Default)]
+pub struct Workflow {
+    nodes: BTreeMap<String, TaskSignature>,
+    edges: Vec<Edge>,
+}
+
+impl Workflow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_node(mut self, key: impl Into<String>, signature: TaskSignature) -> Self {
+        self.nodes.insert(key.into(), signature);
+        self
+    }
+
+    /// Runs `to` only if `from` ends as `condition` requires.
+    pub fn with_edge(
+        mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        condition: EdgeCondition,
+    ) -> Self {
+        self.edges.push(Edge {
+            from: from.into(),
+            to: to.into(),
+            condition,
+        });
+        self
+    }
+
+    pub fn on_success(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.with_edge(from, to, EdgeCondition::OnSuccess)
+    }
+
+    pub fn on_failure(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.with_edge(from, to, EdgeCondition::OnFailure)
+    }
+
+    pub fn always(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.with_edge(from, to, EdgeCondition::Always)
+    }
+
+    /// Checks that the workflow has nodes, that edges join known nodes and
+    /// that there are no cycles.
+    pub fn validate(&self) -> Result<(), TaskError> {
+        if self.nodes.is_empty() {
+            return Err(TaskError::ValidationError(
+                "Workflow has no nodes".to_string(),
+            ));
+        }
+        let mut incoming: HashMap<&str, usize> =
+            self.nodes.keys().map(|key| (key.as_str(), 0)).collect();
+        for edge in &self.edges {
+            for key in [&edge.from, &edge.to] {
+                if !self.nodes.contains_key(key) {
+                    return Err(TaskError::ValidationError(format!(
+                        "Edge refers to unknown node {}",
+                        key
+                    )));
+                }
+            }
+            *incoming.get_mut(edge.to.as_str()).unwrap() += 1;
+        }
+
+        // Kahn's algorithm: every node is reached only if there is no cycle.
+        let mut ready: VecDeque<&str> = incoming
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(key, _)| *key)
+            .collect();
+        let mut visited = 0;
+        while let Some(key) = ready.pop_front() {
+            visited += 1;
+            for edge in self.edges.iter().filter(|edge| edge.from == key) {
+                let count = incoming.get_mut(edge.to.as_str()).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(&edge.to);
+                }
+            }
+        }
+        if visited < self.nodes.len() {
+            return Err(TaskError::ValidationError(
+                "Workflow has a cycle".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeState {
+    /// Waiting for its dependencies.
+    Pending,
+    Enqueued,
+    Completed,
+    Failed,
+    /// Not run because a dependency did not end as required.
+    Skipped,
+    Cancelled,
+}
+
+impl NodeState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, NodeState::Pending | NodeState::Enqueued)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorkflowStatus {
+    Running,
+    Completed,
+    /// A node failed without an edge handling its failure.
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowNode {
+    task_id: Uuid,
+    signature: TaskSignature,
+    pub(crate) state: NodeState,
+}
+
+impl WorkflowNode {
+    /// Id of the node's task once it is enqueued.
+    pub fn task_id(&self) -> Uuid {
+        self.task_id
+    }
+
+    pub fn signature(&self) -> &TaskSignature {
+        &self.signature
+    }
+
+    pub fn state(&self) -> NodeState {
+        self.state
+    }
+}
+
+/// A workflow as persisted in storage, with the state of each node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowRecord {
+    id: Uuid,
+    pub(crate) nodes: BTreeMap<String, WorkflowNode>,
+    edges: Vec<Edge>,
+    #[serde(default)]
+    pub(crate) cancelled: bool,
+    created_at: DateTime<Utc>,
+}
+
+impl WorkflowRecord {
+    pub fn new(workflow: Workflow) -> Self {
+        let nodes = workflow
+            .nodes
+            .into_iter()
+            .map(|(key, signature)| {
+                let node = WorkflowNode {
+                    task_id: Uuid::new_v4(),
+                    signature,
+                    state: NodeState::Pending,
+                };
+                (key, node)
+            })
+            .collect();
+        Self {
+            id: Uuid::new_v4(),
+            nodes,
+            edges: workflow.edges,
+            cancelled: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn nodes(&self) -> &BTreeMap<String, WorkflowNode> {
+        &self.nodes
+    }
+
+    pub fn node(&self, key: &str) -> Option<&WorkflowNode> {
+        self.nodes.get(key)
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    /// Whether the workflow was cancelled; none of its nodes run from then
+    /// on.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    pub fn status(&self) -> WorkflowStatus {
+        let states = || self.nodes.values().map(|node| node.state);
+        if self.cancelled || states().any(|state| state == NodeState::Cancelled) {
+            return WorkflowStatus::Cancelled;
+        }
+        if !states().all(|state| state.is_finished()) {
+            return WorkflowStatus::Running;
+        }
+        let unhandled_failure = self.nodes.iter().any(|(key, node)| {
+            node.state == NodeState::Failed
+                && !self
+                    .edges
+                    .iter()
+                    .any(|edge| edge.from == *key && edge.condition != EdgeCondition::OnSuccess)
+        });
+        if unhandled_failure {
+            WorkflowStatus::Failed
+        } else {
+            WorkflowStatus::Completed
+        }
+    }
+
+    /// Decides pending nodes whose dependencies have all finished: `Some(true)`
+    /// if they should run, `Some(false)` if they are skipped and `None` if the
+    /// workflow was cancelled under them.
+    pub(crate) fn resolved_nodes(&self) -> Vec<(String, Option<bool>)> {
+        let mut resolved = Vec::new();
+        for (key, node) in &self.nodes {
+            if node.state != NodeState::Pending {
+                continue;
+            }
+            let mut run = Some(true);
+            let mut finished = true;
+            for edge in self.edges.iter().filter(|edge| edge.to == *key) {
+                let source = self.nodes[&edge.from].state;
+                if source == NodeState::Cancelled {
+                    run = None;
+                    break;
+                }
+                match edge.is_satisfied(source) {
+                    Some(satisfied) => run = run.map(|run| run && satisfied),
+                    None => finished = false,
+                }
+            }
+            if finished || run.is_none() {
+                resolved.push((key.clone(), run));
+            }
+        }
+        resolved
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::Stream;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use super::event::TaskEvent;
+use crate::core::TaskError;
+
+const EVENT_CAPACITY: usize = 1024;
+
+/// Forwards events beyond the local process.
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, event: &TaskEvent) -> Result<(), TaskError>;
+}
+
+/// Fans task events out to local subscribers and any remote publishers.
+///
+/// Cheap to clone; clones share the same subscribers.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<TaskEvent>,
+    publishers: Arc<Vec<Arc<dyn EventPublisher>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+
+        Self {
+            sender,
+            publishers: Arc::new(Vec::new()),
+        }
+    }
+
+    pub fn with_publisher<P: EventPublisher + 'static>(mut self, publisher: P) -> Self {
+        Arc::make_mut(&mut self.publishers).push(Arc::new(publisher));
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Local events as a stream. Events missed by a slow consumer are skipped.
+    pub fn stream(&self) -> impl Stream<Item = TaskEvent> + Send + 'static {
+        futures_util::stream::unfold(self.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Delivers an event. Failing to publish never fails the task itself.
+    pub async fn emit(&self, event: TaskEvent) {
+        for publisher in self.publishers.iter() {
+            if let Err(e) = publisher.publish(&event).await {
+                warn!("Failed to publish {:?} event: {:?}", event.kind, e);
+            }
+        }
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::core::Task;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TaskEventKind {
+    Enqueued,
+    Started,
+    Retried,
+    Succeeded,
+    Failed,
+    Cancelled,
+    TimedOut,
+    Ignored,
+}
+
+/// A state change of a single task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskEvent {
+    pub task_id: Uuid,
+    pub task_name: String,
+    pub kind: TaskEventKind,
+    pub timestamp: DateTime<Utc>,
+    pub worker_id: Option<String>,
+    pub retries: u32,
+    /// Why the task failed, was retried or was cancelled.
+    pub reason: Option<String>,
+}
+
+impl TaskEvent {
+    pub fn new(task: &Task, kind: TaskEventKind) -> Self {
+        Self {
+            task_id: task.id(),
+            task_name: task.name().to_string(),
+            kind,
+            timestamp: Utc::now(),
+            worker_id: None,
+            retries: task.retries(),
+            reason: None,
+        }
+    }
+
+    pub fn with_worker_id(mut self, worker_id: Option<&str>) -> Self {
+        self.worker_id = worker_id.map(str::to_string);
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
@@ -0,0 +1,6 @@
+mod bus;
+mod event;
+pub mod redis;
+
+pub use bus::{EventBus, EventPublisher};
+pub use event::{TaskEvent, TaskEventKind};
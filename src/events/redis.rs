@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use futures_util::{Stream, StreamExt};
+use redis::Client;
+use tracing::warn;
+
+use super::bus::EventPublisher;
+use super::event::TaskEvent;
+use crate::core::TaskError;
+
+/// Publishes task events on a Redis pub/sub channel.
+pub struct RedisEventPublisher {
+    client: Client,
+    channel: String,
+}
+
+impl RedisEventPublisher {
+    pub fn new(redis_url: &str, channel: &str) -> Result<Self, redis::RedisError> {
+        Ok(Self {
+            client: Client::open(redis_url)?,
+            channel: channel.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventPublisher for RedisEventPublisher {
+    async fn publish(&self, event: &TaskEvent) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: i64 = redis::cmd("PUBLISH")
+            .arg(&self.channel)
+            .arg(serde_json::to_string(event)?)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Receives task events published by other processes.
+pub struct RedisEventSubscriber {
+    client: Client,
+    channel: String,
+}
+
+impl RedisEventSubscriber {
+    pub fn new(redis_url: &str, channel: &str) -> Result<Self, redis::RedisError> {
+        Ok(Self {
+            client: Client::open(redis_url)?,
+            channel: channel.to_string(),
+        })
+    }
+
+    pub async fn subscribe(&self) -> Result<impl Stream<Item = TaskEvent>, TaskError> {
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(&self.channel).await?;
+
+        Ok(pubsub.into_on_message().filter_map(|msg| async move {
+            let payload: String = msg.get_payload().ok()?;
+            match serde_json::from_str(&payload) {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    warn!("Ignoring malformed task event: {:?}", e);
+                    None
+                }
+            }
+        }))
+    }
+}
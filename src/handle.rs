@@ -0,0 +1,63 @@
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use crate::core::{Task, TaskError, TaskStatus};
+use crate::storage::Storage;
+
+/// Awaitable reference to an enqueued task.
+///
+/// Awaiting the handle itself is the same as calling [`TaskHandle::wait`].
+#[derive(Clone)]
+pub struct TaskHandle {
+    id: Uuid,
+    storage: Arc<dyn Storage>,
+}
+
+impl TaskHandle {
+    pub fn new(id: Uuid, storage: Arc<dyn Storage>) -> Self {
+        Self { id, storage }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Waits until the task is finished and returns its final state.
+    pub async fn wait(&self) -> Result<Task, TaskError> {
+        self.storage.wait_for_completion(self.id).await
+    }
+
+    pub async fn wait_timeout(&self, timeout: Duration) -> Result<Task, TaskError> {
+        tokio::time::timeout(timeout, self.wait())
+            .await
+            .map_err(|_| TaskError::WaitTimeout(self.id))?
+    }
+
+    /// Waits for the task and returns its result bytes, or an error if it
+    /// did not complete successfully.
+    pub async fn result(&self) -> Result<Vec<u8>, TaskError> {
+        let task = self.wait().await?;
+        match task.status() {
+            TaskStatus::Completed => Ok(task.get_result().unwrap_or_default().to_vec()),
+            TaskStatus::Failed(reason) => Err(TaskError::ExecutionError(reason.clone())),
+            TaskStatus::TimedOut => Err(TaskError::TimedOut(task.id())),
+            status => Err(TaskError::ExecutionError(format!(
+                "{} finished as {:?}",
+                task, status
+            ))),
+        }
+    }
+}
+
+impl IntoFuture for TaskHandle {
+    type Output = Result<Task, TaskError>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { self.wait().await })
+    }
+}
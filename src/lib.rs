@@ -1,5 +1,13 @@
 pub mod broker;
 pub mod core;
+pub mod events;
+pub mod handle;
+pub mod lock;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod producer;
+pub mod scheduler;
 pub mod storage;
 pub mod task_manager;
+pub mod telemetry;
 pub mod worker;
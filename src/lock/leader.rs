@@ -0,0 +1,144 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinHandle,
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::traits::Lock;
+use crate::core::TaskError;
+
+/// How long a leader's lease lasts without renewal.
+pub const DEFAULT_LEASE_TTL: Duration = Duration::from_secs(15);
+
+/// Elects a single leader among every process campaigning for the same key.
+///
+/// The leader renews its lease at a third of the TTL; if it stops renewing,
+/// another candidate takes over once the lease expires.
+pub struct LeaderElector {
+    lock: Arc<dyn Lock>,
+    key: String,
+    holder: String,
+    ttl: Duration,
+    leader_tx: Arc<watch::Sender<bool>>,
+    handle: Option<JoinHandle<()>>,
+    shutdown_tx: broadcast::Sender<()>,
+}
+
+impl LeaderElector {
+    pub fn new(lock: Arc<dyn Lock>, key: &str) -> Self {
+        let (leader_tx, _) = watch::channel(false);
+        let (shutdown_tx, _) = broadcast::channel(1);
+
+        Self {
+            lock,
+            key: key.to_string(),
+            holder: Uuid::new_v4().to_string(),
+            ttl: DEFAULT_LEASE_TTL,
+            leader_tx: Arc::new(leader_tx),
+            handle: None,
+            shutdown_tx,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    pub fn is_leader(&self) -> bool {
+        *self.leader_tx.borrow()
+    }
+
+    /// Watches leadership changes of this candidate.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.leader_tx.subscribe()
+    }
+
+    /// Runs a single election round, returning whether this candidate leads.
+    pub async fn campaign(&self) -> Result<bool, TaskError> {
+        campaign(
+            self.lock.as_ref(),
+            &self.key,
+            &self.holder,
+            self.ttl,
+            &self.leader_tx,
+        )
+        .await
+    }
+
+    pub fn start(&mut self) {
+        let lock = Arc::clone(&self.lock);
+        let key = self.key.clone();
+        let holder = self.holder.clone();
+        let ttl = self.ttl;
+        let leader_tx = Arc::clone(&self.leader_tx);
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        self.handle = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval((ttl / 3).max(Duration::from_millis(1)));
+            loop {
+                tokio::select! {
+                    Ok(_) = shutdown_rx.recv() => {
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let result = campaign(lock.as_ref(), &key, &holder, ttl, &leader_tx).await;
+                        if let Err(e) = result {
+                            error!("Leader election for {} failed: {:?}", key, e);
+                        }
+                    }
+                }
+            }
+        }));
+    }
+
+    /// Stops campaigning and hands the lease over if this candidate leads.
+    pub async fn shutdown(&mut self) -> Result<(), TaskError> {
+        let _ = self.shutdown_tx.send(());
+
+        if let Some(handle) = self.handle.take() {
+            handle
+                .await
+                .map_err(|e| TaskError::ShutdownError(e.to_string()))?;
+        }
+        if self.leader_tx.send_replace(false) {
+            self.lock.release(&self.key, &self.holder).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn campaign(
+    lock: &dyn Lock,
+    key: &str,
+    holder: &str,
+    ttl: Duration,
+    leader_tx: &watch::Sender<bool>,
+) -> Result<bool, TaskError> {
+    let was_leader = *leader_tx.borrow();
+    let result = if was_leader {
+        lock.renew(key, holder, ttl).await
+    } else {
+        lock.try_acquire(key, holder, ttl).await
+    };
+
+    // Without a confirmed lease another candidate may already lead, so any
+    // error means stepping down.
+    let is_leader = *result.as_ref().unwrap_or(&false);
+    if is_leader != was_leader {
+        if is_leader {
+            info!("{} became leader for {}", holder, key);
+        } else {
+            warn!("{} lost leadership for {}", holder, key);
+        }
+        leader_tx.send_replace(is_leader);
+    }
+    result
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use super::traits::Lock;
+use crate::core::TaskError;
+
+/// In-process lock, mostly useful for tests and single-process setups.
+pub struct MemoryLock {
+    leases: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl MemoryLock {
+    pub fn new() -> Self {
+        Self {
+            leases: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for MemoryLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Lock for MemoryLock {
+    async fn try_acquire(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, TaskError> {
+        let mut leases = self.leases.lock().await;
+        let now = Instant::now();
+
+        if let Some((_, expires_at)) = leases.get(key) {
+            if *expires_at > now {
+                return Ok(false);
+            }
+        }
+        leases.insert(key.to_string(), (holder.to_string(), now + ttl));
+        Ok(true)
+    }
+
+    async fn renew(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, TaskError> {
+        let mut leases = self.leases.lock().await;
+        let now = Instant::now();
+
+        match leases.get_mut(key) {
+            Some((owner, expires_at)) if owner == holder && *expires_at > now => {
+                *expires_at = now + ttl;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn release(&self, key: &str, holder: &str) -> Result<bool, TaskError> {
+        let mut leases = self.leases.lock().await;
+
+        match leases.get(key) {
+            Some((owner, _)) if owner == holder => {
+                leases.remove(key);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod leader;
+pub mod memory;
+pub mod redis;
+pub mod traits;
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use redis::{Client, Script};
+use std::time::Duration;
+
+use super::traits::Lock;
+use crate::core::TaskError;
+
+const RENEW_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+";
+
+const RELEASE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/// Lock backed by `SET NX PX`, shared by every process using the same Redis.
+pub struct RedisLock {
+    client: Client,
+    prefix: String,
+    renew_script: Script,
+    release_script: Script,
+}
+
+impl RedisLock {
+    pub fn new(redis_url: &str, prefix: &str) -> Result<Self, redis::RedisError> {
+        Ok(Self {
+            client: Client::open(redis_url)?,
+            prefix: prefix.to_string(),
+            renew_script: Script::new(RENEW_SCRIPT),
+            release_script: Script::new(RELEASE_SCRIPT),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.prefix, key)
+    }
+}
+
+/// A ttl under a millisecond still holds the lease for one: PX must be
+/// positive, and PEXPIRE 0 would drop the lease at once.
+fn ttl_millis(ttl: Duration) -> u64 {
+    ttl.as_millis().max(1) as u64
+}
+
+#[async_trait]
+impl Lock for RedisLock {
+    async fn try_acquire(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(self.key(key))
+            .arg(holder)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl_millis(ttl))
+            .query_async(&mut conn)
+            .await?;
+        Ok(acquired.is_some())
+    }
+
+    async fn renew(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let renewed: i64 = self
+            .renew_script
+            .key(self.key(key))
+            .arg(holder)
+            .arg(ttl_millis(ttl))
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(renewed == 1)
+    }
+
+    async fn release(&self, key: &str, holder: &str) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let released: i64 = self
+            .release_script
+            .key(self.key(key))
+            .arg(holder)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(released == 1)
+    }
+}
@@ -0,0 +1,18 @@
+use std::time::Duration;
+
+use crate::core::TaskError;
+use async_trait::async_trait;
+
+/// A named lease held by at most one holder at a time.
+///
+/// Leases expire on their own after `ttl`, so a holder that dies without
+/// releasing only blocks others until the lease runs out.
+#[async_trait]
+pub trait Lock: Send + Sync {
+    /// Takes the lease if nobody currently holds it.
+    async fn try_acquire(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, TaskError>;
+    /// Extends the lease if `holder` still owns it.
+    async fn renew(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, TaskError>;
+    /// Gives up the lease if `holder` still owns it.
+    async fn release(&self, key: &str, holder: &str) -> Result<bool, TaskError>;
+}
@@ -0,0 +1,183 @@
+//! Prometheus metrics for tasks and workers.
+//!
+//! Counters are fed by task events, so a [`Metrics`] added to the
+//! `TaskManagerBuilder` counts tasks enqueued, retried and finished by this
+//! process. Timings and worker gauges are recorded by the worker pool.
+
+use std::io;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+use crate::core::TaskError;
+use crate::events::{EventPublisher, TaskEvent, TaskEventKind};
+
+/// Bucket bounds in seconds, from a few milliseconds up to an hour.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0,
+];
+
+/// Task and worker metrics, registered in their own [`Registry`].
+///
+/// Cheap to clone; clones update the same metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    enqueued: IntCounterVec,
+    succeeded: IntCounterVec,
+    failed: IntCounterVec,
+    retried: IntCounterVec,
+    queue_wait: HistogramVec,
+    execution: HistogramVec,
+    queue_depth: IntGauge,
+    busy_workers: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, TaskError> {
+        Self::with_registry(Registry::new())
+    }
+
+    /// Registers the metrics in `registry`, e.g. one shared with the rest of
+    /// the application.
+    pub fn with_registry(registry: Registry) -> Result<Self, TaskError> {
+        let counter = |name: &str, help: &str| {
+            let counter =
+                IntCounterVec::new(Opts::new(name, help), &["task"]).map_err(metrics_error)?;
+            registry
+                .register(Box::new(counter.clone()))
+                .map_err(metrics_error)?;
+            Ok::<_, TaskError>(counter)
+        };
+        let histogram = |name: &str, help: &str| {
+            let opts = HistogramOpts::new(name, help).buckets(DURATION_BUCKETS.to_vec());
+            let histogram = HistogramVec::new(opts, &["task"]).map_err(metrics_error)?;
+            registry
+                .register(Box::new(histogram.clone()))
+                .map_err(metrics_error)?;
+            Ok::<_, TaskError>(histogram)
+        };
+        let gauge = |name: &str, help: &str| {
+            let gauge = IntGauge::new(name, help).map_err(metrics_error)?;
+            registry
+                .register(Box::new(gauge.clone()))
+                .map_err(metrics_error)?;
+            Ok::<_, TaskError>(gauge)
+        };
+
+        Ok(Self {
+            enqueued: counter("bg_coor_tasks_enqueued_total", "Tasks enqueued")?,
+            succeeded: counter("bg_coor_tasks_succeeded_total", "Tasks that succeeded")?,
+            failed: counter("bg_coor_tasks_failed_total", "Tasks that failed for good")?,
+            retried: counter("bg_coor_tasks_retried_total", "Task retries scheduled")?,
+            queue_wait: histogram(
+                "bg_coor_task_queue_wait_seconds",
+                "Time tasks spent waiting to be picked up once due",
+            )?,
+            execution: histogram("bg_coor_task_execution_seconds", "Time spent running tasks")?,
+            queue_depth: gauge("bg_coor_queue_depth", "Tasks waiting in the broker")?,
+            busy_workers: gauge("bg_coor_busy_workers", "Workers running a task")?,
+            registry,
+        })
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Renders all metrics of the registry in the text exposition format.
+    pub fn render(&self) -> Result<String, TaskError> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(metrics_error)?;
+        String::from_utf8(buffer).map_err(metrics_error)
+    }
+
+    /// Serves [`Metrics::render`] on `GET /metrics` until the listener fails.
+    pub async fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let metrics = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = metrics.respond(stream).await {
+                    warn!("Failed to serve metrics: {:?}", e);
+                }
+            });
+        }
+    }
+
+    async fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        // Only the request line matters; the rest of the request is ignored.
+        let mut request = [0; 1024];
+        let read = stream.read(&mut request).await?;
+        let request = String::from_utf8_lossy(&request[..read]);
+        let path = request.split_whitespace().nth(1);
+
+        let (status, body) = match (request.starts_with("GET "), path) {
+            (true, Some("/metrics")) => match self.render() {
+                Ok(body) => ("200 OK", body),
+                Err(e) => ("500 Internal Server Error", e.to_string()),
+            },
+            _ => ("404 Not Found", String::new()),
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            TextEncoder::new().format_type(),
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    pub(crate) fn observe_queue_wait(&self, task: &str, wait: Duration) {
+        self.queue_wait
+            .with_label_values(&[task])
+            .observe(wait.as_secs_f64());
+    }
+
+    pub(crate) fn observe_execution(&self, task: &str, duration: Duration) {
+        self.execution
+            .with_label_values(&[task])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub(crate) fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.set(depth as i64);
+    }
+
+    pub(crate) fn worker_busy(&self) {
+        self.busy_workers.inc();
+    }
+
+    pub(crate) fn worker_idle(&self) {
+        self.busy_workers.dec();
+    }
+}
+
+#[async_trait]
+impl EventPublisher for Metrics {
+    async fn publish(&self, event: &TaskEvent) -> Result<(), TaskError> {
+        let counter = match event.kind {
+            TaskEventKind::Enqueued => &self.enqueued,
+            TaskEventKind::Succeeded => &self.succeeded,
+            TaskEventKind::Failed | TaskEventKind::TimedOut => &self.failed,
+            TaskEventKind::Retried => &self.retried,
+            _ => return Ok(()),
+        };
+        counter.with_label_values(&[&event.task_name]).inc();
+        Ok(())
+    }
+}
+
+fn metrics_error(e: impl std::fmt::Display) -> TaskError {
+    TaskError::Other(e.to_string())
+}
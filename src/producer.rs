@@ -0,0 +1,649 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::broker::traits::Broker;
+use crate::core::{
+    Chain, Chord, EnqueueOptions, Group, GroupRecord, GroupStatus, NodeState, OnDuplicate, Saga,
+    SagaPosition, SagaRecord, SagaStatus, Step, Task, TaskError, TaskSignature, TaskStatus,
+    Workflow, WorkflowRecord, WorkflowStatus,
+};
+use crate::events::{EventBus, TaskEvent, TaskEventKind};
+use crate::storage::Storage;
+
+/// Wraps the enqueueing of every new task, before it reaches the broker.
+///
+/// The producer-side counterpart of [`crate::worker::middleware::Middleware`]:
+/// each hook may change the task, reject it with an error, or call the rest
+/// of the chain through [`EnqueueNext::run`] and see its outcome.
+#[async_trait]
+pub trait EnqueueMiddleware: Send + Sync {
+    async fn handle(&self, task: &mut Task, next: EnqueueNext<'_>) -> Result<(), TaskError> {
+        self.before_enqueue(task).await?;
+        next.run(task).await?;
+        self.after_enqueue(task).await
+    }
+
+    /// Runs before the rest of the chain unless `handle` is overridden. An
+    /// error rejects the task.
+    async fn before_enqueue(&self, _task: &mut Task) -> Result<(), TaskError> {
+        Ok(())
+    }
+
+    /// Runs once the task has been stored and pushed, unless `handle` is
+    /// overridden.
+    async fn after_enqueue(&self, _task: &Task) -> Result<(), TaskError> {
+        Ok(())
+    }
+}
+
+/// The rest of an enqueue chain, ending in storage and the broker.
+pub struct EnqueueNext<'a> {
+    middlewares: &'a [Arc<dyn EnqueueMiddleware>],
+    producer: &'a Producer,
+}
+
+impl EnqueueNext<'_> {
+    pub async fn run(self, task: &mut Task) -> Result<(), TaskError> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => {
+                let next = EnqueueNext {
+                    middlewares: rest,
+                    ..self
+                };
+                middleware.handle(task, next).await
+            }
+            None => self.producer.submit(task).await,
+        }
+    }
+}
+
+/// Creates tasks from signatures and hands them to the broker.
+///
+/// Cheap to clone; every component that enqueues work shares the same
+/// producer so tasks are recorded the same way regardless of their origin.
+#[derive(Clone)]
+pub struct Producer {
+    broker: Arc<dyn Broker>,
+    storage: Arc<dyn Storage>,
+    events: EventBus,
+    middlewares: Arc<Vec<Arc<dyn EnqueueMiddleware>>>,
+}
+
+impl Producer {
+    pub fn new(broker: Arc<dyn Broker>, storage: Arc<dyn Storage>) -> Self {
+        Self {
+            broker,
+            storage,
+            events: EventBus::new(),
+            middlewares: Arc::new(Vec::new()),
+        }
+    }
+
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Runs every new task through `middleware`, after the ones added
+    /// before it.
+    pub fn with_middleware<M: EnqueueMiddleware + 'static>(mut self, middleware: M) -> Self {
+        Arc::make_mut(&mut self.middlewares).push(Arc::new(middleware));
+        self
+    }
+
+    /// Replaces the enqueue chain, outermost first.
+    pub fn with_middlewares(mut self, middlewares: Arc<Vec<Arc<dyn EnqueueMiddleware>>>) -> Self {
+        self.middlewares = middlewares;
+        self
+    }
+
+    /// Enqueues a task. With [`EnqueueOptions::with_unique`], a duplicate
+    /// of a task enqueued within the window is not enqueued again.
+    pub async fn enqueue(
+        &self,
+        signature: TaskSignature,
+        options: EnqueueOptions,
+    ) -> Result<Uuid, TaskError> {
+        let unique = options.unique.clone();
+        let task = build_task(&signature, options)?;
+        let Some(unique) = unique else {
+            return self.enqueue_task(task).await;
+        };
+
+        let key = unique.key_for(&signature);
+        let id = task.id();
+        if let Some(existing) = self.broker.lock_unique(&key, id, unique.window()).await? {
+            return match unique.on_duplicate() {
+                OnDuplicate::ReturnExisting => Ok(existing),
+                OnDuplicate::Reject => Err(TaskError::Duplicate(existing)),
+            };
+        }
+        let result = self.enqueue_task(task).await;
+        if result.is_err() {
+            // The key must not point duplicates at a task that never existed.
+            self.broker.unlock_unique(&key, id).await?;
+        }
+        result
+    }
+
+    /// Enqueues the first task of `chain`; the others follow as each one
+    /// completes. `options` apply to every task. Returns the ids of all the
+    /// tasks, the last one holding the chain's result.
+    pub async fn enqueue_chain(
+        &self,
+        chain: Chain,
+        options: EnqueueOptions,
+    ) -> Result<Vec<Uuid>, TaskError> {
+        let mut steps: Vec<Step> = chain.signatures.into_iter().map(Step::new).collect();
+        if steps.is_empty() {
+            return Err(TaskError::InvalidArgument("Empty chain".to_string()));
+        }
+        let ids = steps.iter().map(Step::id).collect();
+
+        let first = steps.remove(0);
+        let mut task = build_task(first.signature(), options)?;
+        task.id = first.id();
+        task.chain = steps;
+        self.enqueue_task(task).await?;
+        Ok(ids)
+    }
+
+    /// Enqueues every task of `group` at once. `options` apply to every
+    /// task.
+    pub async fn enqueue_group(
+        &self,
+        group: Group,
+        options: EnqueueOptions,
+    ) -> Result<GroupRecord, TaskError> {
+        self.start_group(group, None, options).await
+    }
+
+    /// Enqueues the group of `chord`; its callback is enqueued once every
+    /// member has finished. `options` apply to every task.
+    pub async fn enqueue_chord(
+        &self,
+        chord: Chord,
+        options: EnqueueOptions,
+    ) -> Result<GroupRecord, TaskError> {
+        let callback = Step::new(chord.callback);
+        self.start_group(chord.group, Some(callback), options).await
+    }
+
+    async fn start_group(
+        &self,
+        group: Group,
+        callback: Option<Step>,
+        options: EnqueueOptions,
+    ) -> Result<GroupRecord, TaskError> {
+        let members: Vec<Task> = group
+            .signatures
+            .iter()
+            .map(|signature| build_task(signature, options.clone()))
+            .collect::<Result<_, _>>()?;
+        let mut record = GroupRecord::new(members.iter().map(Task::id).collect(), callback);
+        if members.is_empty() {
+            // No member will ever finish it.
+            record.set_status(GroupStatus::Completed);
+        }
+
+        // Stored first so that members finishing right away find their group.
+        self.storage.store_group(&record).await?;
+        if let (Some(callback), true) = (record.callback(), members.is_empty()) {
+            let mut task = build_task(callback.signature(), options)?;
+            task.id = callback.id();
+            append_arg(&mut task, Value::Array(Vec::new()))?;
+            self.enqueue_task(task).await?;
+        }
+        for mut task in members {
+            task.group_id = Some(record.id());
+            self.enqueue_task(task).await?;
+        }
+        Ok(record)
+    }
+
+    /// Validates `workflow`, stores it and enqueues the nodes without
+    /// dependencies. `options` apply to every task.
+    pub async fn enqueue_workflow(
+        &self,
+        workflow: Workflow,
+        options: EnqueueOptions,
+    ) -> Result<WorkflowRecord, TaskError> {
+        workflow.validate()?;
+        let record = WorkflowRecord::new(workflow);
+        self.storage.store_workflow(&record).await?;
+
+        for (key, id, signature) in self.release_nodes(record.clone()).await? {
+            let mut task = build_task(&signature, options.clone())?;
+            task.id = id;
+            task.workflow_id = Some(record.id());
+            task.workflow_node = Some(key);
+            self.enqueue_task(task).await?;
+        }
+        Ok(record)
+    }
+
+    /// Stores `saga` and enqueues its first step; the others follow as each
+    /// one completes. `options` apply to every task.
+    pub async fn enqueue_saga(
+        &self,
+        saga: Saga,
+        options: EnqueueOptions,
+    ) -> Result<SagaRecord, TaskError> {
+        if saga.steps.is_empty() {
+            return Err(TaskError::InvalidArgument("Empty saga".to_string()));
+        }
+        let record = SagaRecord::new(saga);
+        self.storage.store_saga(&record).await?;
+
+        let first = record.steps()[0].action();
+        let mut task = build_task(first.signature(), options)?;
+        task.id = first.id();
+        task.saga_id = Some(record.id());
+        task.saga_position = Some(SagaPosition::Action(0));
+        self.enqueue_task(task).await?;
+        Ok(record)
+    }
+
+    /// Cancels every node of a workflow that has not finished. Returns
+    /// `false` if the workflow was no longer running.
+    pub async fn cancel_workflow(&self, id: Uuid) -> Result<bool, TaskError> {
+        let Some(workflow) = self.storage.load_workflow(id).await? else {
+            return Err(TaskError::NotFound(id.to_string()));
+        };
+        if workflow.status() != WorkflowStatus::Running || !self.storage.cancel_workflow(id).await?
+        {
+            return Ok(false);
+        }
+
+        // No node can be released from here on, so a fresh copy shows every
+        // node that was.
+        let Some(workflow) = self.storage.load_workflow(id).await? else {
+            return Ok(true);
+        };
+        for key in workflow.nodes().keys() {
+            self.storage
+                .transition_workflow_node(id, key, &[NodeState::Pending], NodeState::Cancelled)
+                .await?;
+        }
+        for node in workflow.nodes().values() {
+            if node.state() == NodeState::Enqueued {
+                // A node released just before may not have its task stored
+                // yet; the revocation stops it once it is.
+                self.storage.revoke_task(node.task_id()).await?;
+                match self.cancel(node.task_id()).await {
+                    Ok(_) | Err(TaskError::NotFound(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Claims the pending nodes of `workflow` whose dependencies have
+    /// resolved and returns those that should run. Skipping a node may
+    /// resolve others, so this repeats until nothing changes.
+    async fn release_nodes(
+        &self,
+        mut workflow: WorkflowRecord,
+    ) -> Result<Vec<(String, Uuid, TaskSignature)>, TaskError> {
+        let mut released = Vec::new();
+        loop {
+            let mut latest = None;
+            for (key, run) in workflow.resolved_nodes() {
+                let state = match run {
+                    Some(true) => NodeState::Enqueued,
+                    Some(false) => NodeState::Skipped,
+                    None => NodeState::Cancelled,
+                };
+                let Some(updated) = self
+                    .storage
+                    .transition_workflow_node(workflow.id(), &key, &[NodeState::Pending], state)
+                    .await?
+                else {
+                    continue;
+                };
+                if state == NodeState::Enqueued {
+                    let node = &updated.nodes()[&key];
+                    released.push((key, node.task_id(), node.signature().clone()));
+                }
+                latest = Some(updated);
+            }
+            match latest {
+                Some(updated) => workflow = updated,
+                None => return Ok(released),
+            }
+        }
+    }
+
+    /// Moves the workflows `task` belongs to forward once it has finished:
+    /// enqueues the next step of its chain, the callback of its chord if it
+    /// was the last member to finish, and the workflow nodes it unblocks.
+    pub(crate) async fn advance(&self, task: &Task) -> Result<(), TaskError> {
+        if let (Some(workflow_id), Some(key)) = (task.workflow_id, task.workflow_node.as_deref()) {
+            let state = match task.status() {
+                TaskStatus::Completed => NodeState::Completed,
+                TaskStatus::Cancelled => NodeState::Cancelled,
+                TaskStatus::Ignored => NodeState::Skipped,
+                _ => NodeState::Failed,
+            };
+            let updated = self
+                .storage
+                .transition_workflow_node(workflow_id, key, &[NodeState::Enqueued], state)
+                .await?;
+            if let Some(workflow) = updated {
+                for (key, id, signature) in self.release_nodes(workflow).await? {
+                    let mut next = follow_up(task, id, &signature);
+                    next.workflow_id = Some(workflow_id);
+                    next.workflow_node = Some(key);
+                    self.enqueue_task(next).await?;
+                }
+            }
+        }
+
+        if let (Some(saga_id), Some(position)) = (task.saga_id, task.saga_position) {
+            self.advance_saga(task, saga_id, position).await?;
+        }
+
+        if let Some(parent_id) = task.parent_id {
+            if self
+                .storage
+                .finish_group_member(parent_id, task.id())
+                .await?
+            {
+                self.resume(parent_id).await?;
+            }
+        }
+
+        if task.status() == &TaskStatus::Completed {
+            if let Some((next, rest)) = task.chain.split_first() {
+                let mut follow_up = follow_up(task, next.id(), next.signature());
+                append_arg(&mut follow_up, task.result_value())?;
+                follow_up.chain = rest.to_vec();
+                self.enqueue_task(follow_up).await?;
+            }
+        } else {
+            let status = match task.status() {
+                TaskStatus::Cancelled => TaskStatus::Cancelled,
+                _ => TaskStatus::Failed(format!("Chain step {} did not complete", task.id())),
+            };
+            for step in &task.chain {
+                let follow_up = follow_up(task, step.id(), step.signature());
+                self.store_unrun(follow_up, status.clone()).await?;
+            }
+        }
+
+        let Some(group_id) = task.group_id else {
+            return Ok(());
+        };
+        if !self
+            .storage
+            .finish_group_member(group_id, task.id())
+            .await?
+        {
+            return Ok(());
+        }
+        let Some(mut group) = self.storage.load_group(group_id).await? else {
+            return Ok(());
+        };
+
+        let mut results = Vec::with_capacity(group.members().len());
+        let mut incomplete = None;
+        for id in group.members() {
+            match self.storage.load_task(*id).await? {
+                Some(member) if member.status() == &TaskStatus::Completed => {
+                    results.push(member.result_value())
+                }
+                _ => {
+                    incomplete = Some(*id);
+                    break;
+                }
+            }
+        }
+        let status = match incomplete {
+            Some(id) => GroupStatus::Failed(format!("Group member {} did not complete", id)),
+            None => GroupStatus::Completed,
+        };
+        group.set_status(status.clone());
+        self.storage.update_group(&group).await?;
+
+        let Some(callback) = group.callback() else {
+            return Ok(());
+        };
+        let mut callback = follow_up(task, callback.id(), callback.signature());
+        if let GroupStatus::Failed(reason) = status {
+            return self.store_unrun(callback, TaskStatus::Failed(reason)).await;
+        }
+        append_arg(&mut callback, Value::Array(results))?;
+        self.enqueue_task(callback).await?;
+        Ok(())
+    }
+
+    /// Records a follow-up task that will never run with its final
+    /// `status`, so that whoever waits for it learns that it will not.
+    async fn store_unrun(&self, mut task: Task, status: TaskStatus) -> Result<(), TaskError> {
+        let kind = match status {
+            TaskStatus::Cancelled => TaskEventKind::Cancelled,
+            _ => TaskEventKind::Failed,
+        };
+        task.set_status(status);
+        self.storage.store_task(&task).await?;
+        self.events.emit(TaskEvent::new(&task, kind)).await;
+        Ok(())
+    }
+
+    /// Runs the next step of a saga once a step completes. Once a step does
+    /// not complete, runs the compensations of the steps before it, the
+    /// latest first.
+    async fn advance_saga(
+        &self,
+        task: &Task,
+        saga_id: Uuid,
+        position: SagaPosition,
+    ) -> Result<(), TaskError> {
+        let Some(mut saga) = self.storage.load_saga(saga_id).await? else {
+            return Ok(());
+        };
+        let completed = task.status() == &TaskStatus::Completed;
+        let undone = match position {
+            SagaPosition::Action(step) if completed => {
+                let Some(next) = saga.steps().get(step + 1) else {
+                    saga.transition(SagaStatus::Completed, step);
+                    return self.storage.store_saga(&saga).await;
+                };
+                let position = SagaPosition::Action(step + 1);
+                return self
+                    .continue_saga(task, &saga, next.action(), position)
+                    .await;
+            }
+            SagaPosition::Action(step) => {
+                saga.transition(SagaStatus::Compensating, step);
+                step
+            }
+            SagaPosition::Compensation(step) if completed => step,
+            SagaPosition::Compensation(step) => {
+                saga.transition(SagaStatus::CompensationFailed, step);
+                return self.storage.store_saga(&saga).await;
+            }
+        };
+        match saga.compensation_before(undone) {
+            Some((step, compensation)) => {
+                let position = SagaPosition::Compensation(step);
+                self.continue_saga(task, &saga, compensation, position)
+                    .await
+            }
+            None => {
+                saga.transition(SagaStatus::Compensated, undone);
+                self.storage.store_saga(&saga).await
+            }
+        }
+    }
+
+    /// Stores `saga` and enqueues its task at `position`, with the same
+    /// settings as `previous`.
+    async fn continue_saga(
+        &self,
+        previous: &Task,
+        saga: &SagaRecord,
+        step: &Step,
+        position: SagaPosition,
+    ) -> Result<(), TaskError> {
+        // Stored first so that the task finishing right away finds it.
+        self.storage.store_saga(saga).await?;
+        let mut task = follow_up(previous, step.id(), step.signature());
+        task.saga_id = Some(saga.id());
+        task.saga_position = Some(position);
+        self.enqueue_task(task).await?;
+        Ok(())
+    }
+
+    /// Records the children a task suspended for, resuming it right away if
+    /// they have all finished already.
+    pub(crate) async fn suspend(&self, task: &Task) -> Result<(), TaskError> {
+        let children: Vec<Uuid> = self
+            .storage
+            .list_children(task.id())
+            .await?
+            .iter()
+            .map(Task::id)
+            .collect();
+        self.storage
+            .store_group(&GroupRecord::children_of(task.id(), children.clone()))
+            .await?;
+
+        // Children that finished before the record existed did not count
+        // themselves off; reload them now that they would.
+        let mut finished = children.is_empty();
+        for id in children {
+            if let Some(child) = self.storage.load_task(id).await? {
+                if child.is_finished() {
+                    finished |= self.storage.finish_group_member(task.id(), id).await?;
+                }
+            }
+        }
+        if finished {
+            self.resume(task.id()).await?;
+        }
+        Ok(())
+    }
+
+    /// Hands a suspended task back to the workers.
+    async fn resume(&self, id: Uuid) -> Result<(), TaskError> {
+        let Some(mut task) = self.storage.load_task(id).await? else {
+            return Ok(());
+        };
+        if task.status() != &TaskStatus::Waiting {
+            return Ok(());
+        }
+        task.set_status(TaskStatus::Pending);
+        self.storage.update_task(&task).await?;
+        self.broker.push(&task).await?;
+        Ok(())
+    }
+
+    async fn enqueue_task(&self, mut task: Task) -> Result<Uuid, TaskError> {
+        #[cfg(feature = "opentelemetry")]
+        crate::telemetry::inject_context(&mut task.headers);
+        if task.is_delayed(task.created_at()) {
+            task.set_status(TaskStatus::Scheduled);
+        }
+
+        EnqueueNext {
+            middlewares: &self.middlewares,
+            producer: self,
+        }
+        .run(&mut task)
+        .await?;
+
+        Ok(task.id())
+    }
+
+    async fn submit(&self, task: &Task) -> Result<(), TaskError> {
+        self.storage.store_task(task).await?;
+        self.broker.push(task).await?;
+        self.events
+            .emit(TaskEvent::new(task, TaskEventKind::Enqueued))
+            .await;
+        Ok(())
+    }
+
+    /// Enqueues a task that failed for good again, under the same id and
+    /// with a fresh retry budget. The task goes through the enqueue chain
+    /// like a new one.
+    pub async fn replay(&self, mut task: Task) -> Result<Uuid, TaskError> {
+        task.retries = 0;
+        task.result = None;
+        task.heartbeat_at = None;
+        task.eta = None;
+        task.retry_delay = None;
+        task.next_retry_at = None;
+        task.set_status(TaskStatus::Pending);
+        self.enqueue_task(task).await
+    }
+
+    /// Revokes a task. A waiting task is dropped from the broker right away;
+    /// a running one is asked to stop through its cancellation token.
+    /// Returns `false` if the task had already finished.
+    pub async fn cancel(&self, id: Uuid) -> Result<bool, TaskError> {
+        let Some(mut task) = self.storage.load_task(id).await? else {
+            return Err(TaskError::NotFound(id.to_string()));
+        };
+        if task.is_finished() {
+            return Ok(false);
+        }
+
+        self.storage.revoke_task(id).await?;
+        let waiting = task.status() == &TaskStatus::Waiting;
+        if self.broker.remove(id).await? || waiting {
+            task.set_status(TaskStatus::Cancelled);
+            self.storage.update_task(&task).await?;
+            self.events
+                .emit(TaskEvent::new(&task, TaskEventKind::Cancelled))
+                .await;
+            self.advance(&task).await?;
+        }
+        Ok(true)
+    }
+}
+
+fn build_task(signature: &TaskSignature, options: EnqueueOptions) -> Result<Task, TaskError> {
+    let mut task = Task::new(
+        signature.name.to_string(),
+        signature.to_bytes(),
+        options.max_retries,
+    );
+    task.set_priority(options.priority);
+    task.set_eta(options.resolve_eta(task.created_at())?);
+    task.set_soft_time_limit(options.soft_time_limit);
+    task.set_hard_time_limit(options.hard_time_limit);
+    task.set_retry_policy(options.retry_policy);
+    task.headers = options.headers;
+    task.parent_id = options.parent_id;
+    task.root_id = options.root_id;
+    Ok(task)
+}
+
+/// Creates a task of the same workflow with the same settings as `previous`.
+fn follow_up(previous: &Task, id: Uuid, signature: &TaskSignature) -> Task {
+    let mut task = Task::new(
+        signature.name.to_string(),
+        signature.to_bytes(),
+        previous.max_retries(),
+    );
+    task.id = id;
+    task.set_priority(previous.priority());
+    task.set_soft_time_limit(previous.soft_time_limit());
+    task.set_hard_time_limit(previous.hard_time_limit());
+    task.set_retry_policy(previous.retry_policy().cloned());
+    task.headers = previous.headers().clone();
+    task
+}
+
+fn append_arg(task: &mut Task, arg: Value) -> Result<(), TaskError> {
+    let mut signature = task.signature()?;
+    signature.args.push(arg);
+    task.set_signature(&signature);
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinHandle,
+};
+use tracing::{error, info};
+
+use crate::{
+    core::{TaskError, Unique},
+    lock::leader::LeaderElector,
+    producer::Producer,
+    storage::Storage,
+};
+
+use super::schedule::PeriodicTask;
+
+/// How often the scheduler checks whether any schedule is due.
+pub const DEFAULT_TICK: Duration = Duration::from_secs(1);
+
+/// Enqueues periodic tasks when their schedules fire.
+///
+/// Last run times are persisted in `Storage`, so a restarted scheduler
+/// neither repeats a run that already happened nor silently skips one that
+/// fell due while it was down. When several processes share a backend, give
+/// each scheduler a `LeaderElector` so only the current leader fires.
+pub struct Scheduler {
+    beat: Beat,
+    elector: Option<LeaderElector>,
+    tick: Duration,
+    handle: Option<JoinHandle<()>>,
+    shutdown_tx: broadcast::Sender<()>,
+}
+
+#[derive(Clone)]
+struct Beat {
+    producer: Producer,
+    storage: Arc<dyn Storage>,
+    entries: Arc<Vec<PeriodicTask>>,
+    leader: Option<watch::Receiver<bool>>,
+}
+
+impl Scheduler {
+    pub fn new(producer: Producer, storage: Arc<dyn Storage>) -> Self {
+        let (shutdown_tx, _) = broadcast::channel(1);
+
+        Self {
+            beat: Beat {
+                producer,
+                storage,
+                entries: Arc::new(Vec::new()),
+                leader: None,
+            },
+            elector: None,
+            tick: DEFAULT_TICK,
+            handle: None,
+            shutdown_tx,
+        }
+    }
+
+    pub fn with_tick(mut self, tick: Duration) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    pub fn with_periodic_task(mut self, task: PeriodicTask) -> Self {
+        Arc::make_mut(&mut self.beat.entries).push(task);
+        self
+    }
+
+    /// Only fires schedules while `elector` holds the leadership.
+    pub fn with_leader_election(mut self, elector: LeaderElector) -> Self {
+        self.beat.leader = Some(elector.subscribe());
+        self.elector = Some(elector);
+        self
+    }
+
+    pub fn leader_elector(&self) -> Option<&LeaderElector> {
+        self.elector.as_ref()
+    }
+
+    pub fn periodic_tasks(&self) -> &[PeriodicTask] {
+        &self.beat.entries
+    }
+
+    pub fn start(&mut self) {
+        if let Some(elector) = self.elector.as_mut() {
+            elector.start();
+        }
+
+        let beat = self.beat.clone();
+        let tick = self.tick;
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        self.handle = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            loop {
+                tokio::select! {
+                    Ok(_) = shutdown_rx.recv() => {
+                        info!("Scheduler is shutting down");
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        beat.run_pending().await;
+                    }
+                }
+            }
+        }));
+    }
+
+    /// Enqueues every schedule that is due, returning how many fired.
+    pub async fn run_pending(&self) -> usize {
+        self.beat.run_pending().await
+    }
+
+    pub async fn shutdown(&mut self) -> Result<(), TaskError> {
+        let _ = self.shutdown_tx.send(());
+
+        if let Some(handle) = self.handle.take() {
+            handle
+                .await
+                .map_err(|e| TaskError::ShutdownError(e.to_string()))?;
+        }
+        if let Some(elector) = self.elector.as_mut() {
+            elector.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Beat {
+    async fn run_pending(&self) -> usize {
+        if let Some(leader) = &self.leader {
+            if !*leader.borrow() {
+                return 0;
+            }
+        }
+
+        let mut fired = 0;
+        for entry in self.entries.iter() {
+            match self.fire_if_due(entry).await {
+                Ok(true) => fired += 1,
+                Ok(false) => {}
+                Err(e) => error!("Failed to run periodic task {}: {:?}", entry.name, e),
+            }
+        }
+        fired
+    }
+
+    async fn fire_if_due(&self, entry: &PeriodicTask) -> Result<bool, TaskError> {
+        let now = Utc::now();
+
+        // A schedule seen for the first time starts counting from now rather
+        // than firing immediately.
+        let Some(last_run) = self.storage.load_schedule_last_run(&entry.name).await? else {
+            self.storage
+                .store_schedule_last_run(&entry.name, now)
+                .await?;
+            return Ok(false);
+        };
+
+        let Some(due) = entry.schedule.last_due(last_run, now) else {
+            return Ok(false);
+        };
+
+        info!("Periodic task {} is due ({})", entry.name, due);
+        // The run is only recorded after it is enqueued. If recording it
+        // fails, the next attempt finds the key of this run and does not
+        // enqueue it again.
+        let mut options = entry.options.clone();
+        if options.unique.is_none() {
+            let key = format!("schedule:{}:{}", entry.name, due.timestamp());
+            options = options.with_unique(Unique::by_key(key));
+        }
+        self.producer
+            .enqueue(entry.signature.clone(), options)
+            .await?;
+        self.storage
+            .store_schedule_last_run(&entry.name, due)
+            .await?;
+
+        Ok(true)
+    }
+}
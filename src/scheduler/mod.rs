@@ -0,0 +1,5 @@
+mod beat;
+mod schedule;
+
+pub use beat::Scheduler;
+pub use schedule::{PeriodicTask, Schedule};
@@ -0,0 +1,121 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::core::{EnqueueOptions, TaskError, TaskSignature};
+
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Fires on a cron expression (`sec min hour day-of-month month
+    /// day-of-week [year]`) evaluated in the given timezone.
+    Cron {
+        expression: Box<cron::Schedule>,
+        timezone: Tz,
+    },
+    /// Fires every `Duration` after the previous run.
+    Interval(Duration),
+}
+
+impl Schedule {
+    pub fn cron(expression: &str) -> Result<Self, TaskError> {
+        Self::cron_with_timezone(expression, Tz::UTC)
+    }
+
+    pub fn cron_with_timezone(expression: &str, timezone: Tz) -> Result<Self, TaskError> {
+        let expression = cron::Schedule::from_str(expression)
+            .map_err(|e| TaskError::InvalidArgument(format!("Invalid cron expression: {}", e)))?;
+
+        Ok(Schedule::Cron {
+            expression: Box::new(expression),
+            timezone,
+        })
+    }
+
+    pub fn interval(interval: Duration) -> Self {
+        Schedule::Interval(interval)
+    }
+
+    /// The first fire time strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Cron {
+                expression,
+                timezone,
+            } => expression
+                .after(&after.with_timezone(timezone))
+                .next()
+                .map(|next| next.with_timezone(&Utc)),
+            Schedule::Interval(interval) => {
+                let interval = chrono::Duration::from_std(*interval).ok()?;
+                after.checked_add_signed(interval)
+            }
+        }
+    }
+
+    /// The latest fire time after `last_run` that is not later than `now`,
+    /// so runs missed while no scheduler was alive collapse into one.
+    pub fn last_due(&self, last_run: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let due = self.next_after(last_run).filter(|due| *due <= now)?;
+
+        match self {
+            Schedule::Cron {
+                expression,
+                timezone,
+            } => {
+                // Steps back from `now` rather than walking every missed run.
+                let latest = expression
+                    .after(&now.with_timezone(timezone))
+                    .next_back()
+                    .map(|latest| latest.with_timezone(&Utc))
+                    .filter(|latest| *latest > due)
+                    .unwrap_or(due);
+                // The step back skips `now` itself when it is a fire time.
+                Some(
+                    self.next_after(latest)
+                        .filter(|next| *next <= now)
+                        .unwrap_or(latest),
+                )
+            }
+            Schedule::Interval(interval) => {
+                let step = (interval.as_millis() as i64).max(1);
+                let missed = (now - due).num_milliseconds() / step;
+                Some(due + chrono::Duration::milliseconds(missed * step))
+            }
+        }
+    }
+}
+
+/// A named schedule that enqueues `signature` every time it fires.
+#[derive(Debug, Clone)]
+pub struct PeriodicTask {
+    pub(crate) name: String,
+    pub(crate) schedule: Schedule,
+    pub(crate) signature: TaskSignature,
+    pub(crate) options: EnqueueOptions,
+}
+
+impl PeriodicTask {
+    pub fn new(name: &str, schedule: Schedule, signature: TaskSignature) -> Self {
+        Self {
+            name: name.to_string(),
+            schedule,
+            signature,
+            options: EnqueueOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: EnqueueOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+}
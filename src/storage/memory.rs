@@ -1,19 +1,44 @@
 // src/storage/memory.rs
 use super::traits::Storage;
-use crate::core::{Task, TaskError};
+use crate::core::{
+    DeadLetter, GroupRecord, NodeState, SagaRecord, Task, TaskError, TaskProgress, TaskStatus,
+    WorkflowRecord,
+};
 use async_trait::async_trait;
-use std::collections::HashMap;
-use tokio::sync::RwLock;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{broadcast, RwLock};
 use uuid::Uuid;
 
 pub struct MemoryStorage {
     tasks: RwLock<HashMap<Uuid, Task>>,
+    schedules: RwLock<HashMap<String, DateTime<Utc>>>,
+    revoked: RwLock<HashSet<Uuid>>,
+    dead_letters: RwLock<HashMap<Uuid, DeadLetter>>,
+    groups: RwLock<HashMap<Uuid, (GroupRecord, HashSet<Uuid>)>>,
+    workflows: RwLock<HashMap<Uuid, WorkflowRecord>>,
+    sagas: RwLock<HashMap<Uuid, SagaRecord>>,
+    completed: broadcast::Sender<Uuid>,
 }
 
 impl MemoryStorage {
     pub fn new() -> Self {
         Self {
             tasks: RwLock::new(HashMap::new()),
+            schedules: RwLock::new(HashMap::new()),
+            revoked: RwLock::new(HashSet::new()),
+            dead_letters: RwLock::new(HashMap::new()),
+            groups: RwLock::new(HashMap::new()),
+            workflows: RwLock::new(HashMap::new()),
+            sagas: RwLock::new(HashMap::new()),
+            completed: broadcast::channel(1024).0,
+        }
+    }
+
+    async fn notify_if_finished(&self, task: &Task) {
+        if task.is_finished() {
+            self.revoked.write().await.remove(&task.id());
+            let _ = self.completed.send(task.id());
         }
     }
 }
@@ -29,6 +54,7 @@ impl Storage for MemoryStorage {
     async fn store_task(&self, task: &Task) -> Result<(), TaskError> {
         let mut tasks = self.tasks.write().await;
         tasks.insert(task.id(), task.clone());
+        self.notify_if_finished(task).await;
         Ok(())
     }
 
@@ -40,12 +66,45 @@ impl Storage for MemoryStorage {
     async fn update_task(&self, task: &Task) -> Result<(), TaskError> {
         let mut tasks = self.tasks.write().await;
         tasks.insert(task.id(), task.clone());
+        self.notify_if_finished(task).await;
         Ok(())
     }
 
     async fn delete_task(&self, id: Uuid) -> Result<(), TaskError> {
         let mut tasks = self.tasks.write().await;
         tasks.remove(&id);
+        self.revoked.write().await.remove(&id);
+        Ok(())
+    }
+
+    async fn touch_heartbeat(&self, id: Uuid, at: DateTime<Utc>) -> Result<(), TaskError> {
+        let mut tasks = self.tasks.write().await;
+        if let Some(task) = tasks.get_mut(&id) {
+            task.heartbeat_at = Some(at);
+        }
+        Ok(())
+    }
+
+    async fn reclaim_task(
+        &self,
+        id: Uuid,
+        stale_before: DateTime<Utc>,
+    ) -> Result<Option<Task>, TaskError> {
+        let mut tasks = self.tasks.write().await;
+        Ok(match tasks.get_mut(&id) {
+            Some(task) if task.is_stale(stale_before) => {
+                task.set_status(TaskStatus::Pending);
+                Some(task.clone())
+            }
+            _ => None,
+        })
+    }
+
+    async fn store_progress(&self, id: Uuid, progress: &TaskProgress) -> Result<(), TaskError> {
+        let mut tasks = self.tasks.write().await;
+        if let Some(task) = tasks.get_mut(&id) {
+            task.progress = Some(progress.clone());
+        }
         Ok(())
     }
 
@@ -53,4 +112,157 @@ impl Storage for MemoryStorage {
         let tasks = self.tasks.read().await;
         Ok(tasks.values().cloned().collect())
     }
+
+    async fn wait_for_completion(&self, id: Uuid) -> Result<Task, TaskError> {
+        // Subscribe before checking so a completion in between is not missed.
+        let mut completed = self.completed.subscribe();
+        loop {
+            if let Some(task) = self.load_task(id).await? {
+                if task.is_finished() {
+                    return Ok(task);
+                }
+            }
+            loop {
+                match completed.recv().await {
+                    Ok(finished) if finished == id => break,
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(_)) => break,
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(TaskError::NotFound(id.to_string()))
+                    }
+                }
+            }
+        }
+    }
+
+    async fn revoke_task(&self, id: Uuid) -> Result<(), TaskError> {
+        // Holding the task map keeps the task from finishing in between.
+        let tasks = self.tasks.read().await;
+        if !tasks.get(&id).is_some_and(Task::is_finished) {
+            self.revoked.write().await.insert(id);
+        }
+        Ok(())
+    }
+
+    async fn is_revoked(&self, id: Uuid) -> Result<bool, TaskError> {
+        let revoked = self.revoked.read().await;
+        Ok(revoked.contains(&id))
+    }
+
+    async fn store_dead_letter(&self, letter: &DeadLetter) -> Result<(), TaskError> {
+        let mut dead_letters = self.dead_letters.write().await;
+        dead_letters.insert(letter.id(), letter.clone());
+        Ok(())
+    }
+
+    async fn load_dead_letter(&self, id: Uuid) -> Result<Option<DeadLetter>, TaskError> {
+        let dead_letters = self.dead_letters.read().await;
+        Ok(dead_letters.get(&id).cloned())
+    }
+
+    async fn list_dead_letters(&self) -> Result<Vec<DeadLetter>, TaskError> {
+        let dead_letters = self.dead_letters.read().await;
+        Ok(dead_letters.values().cloned().collect())
+    }
+
+    async fn delete_dead_letter(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut dead_letters = self.dead_letters.write().await;
+        Ok(dead_letters.remove(&id).is_some())
+    }
+
+    async fn store_group(&self, group: &GroupRecord) -> Result<(), TaskError> {
+        let mut groups = self.groups.write().await;
+        let pending = group.members().iter().copied().collect();
+        groups.insert(group.id(), (group.clone(), pending));
+        Ok(())
+    }
+
+    async fn load_group(&self, id: Uuid) -> Result<Option<GroupRecord>, TaskError> {
+        let groups = self.groups.read().await;
+        Ok(groups.get(&id).map(|(group, _)| group.clone()))
+    }
+
+    async fn update_group(&self, group: &GroupRecord) -> Result<(), TaskError> {
+        let mut groups = self.groups.write().await;
+        if let Some((record, _)) = groups.get_mut(&group.id()) {
+            *record = group.clone();
+        }
+        Ok(())
+    }
+
+    async fn finish_group_member(&self, group_id: Uuid, task_id: Uuid) -> Result<bool, TaskError> {
+        let mut groups = self.groups.write().await;
+        Ok(match groups.get_mut(&group_id) {
+            Some((_, pending)) => pending.remove(&task_id) && pending.is_empty(),
+            None => false,
+        })
+    }
+
+    async fn store_workflow(&self, workflow: &WorkflowRecord) -> Result<(), TaskError> {
+        let mut workflows = self.workflows.write().await;
+        workflows.insert(workflow.id(), workflow.clone());
+        Ok(())
+    }
+
+    async fn load_workflow(&self, id: Uuid) -> Result<Option<WorkflowRecord>, TaskError> {
+        let workflows = self.workflows.read().await;
+        Ok(workflows.get(&id).cloned())
+    }
+
+    async fn transition_workflow_node(
+        &self,
+        id: Uuid,
+        node: &str,
+        from: &[NodeState],
+        to: NodeState,
+    ) -> Result<Option<WorkflowRecord>, TaskError> {
+        let mut workflows = self.workflows.write().await;
+        let Some(workflow) = workflows.get_mut(&id) else {
+            return Ok(None);
+        };
+        if to == NodeState::Enqueued && workflow.cancelled {
+            return Ok(None);
+        }
+        match workflow.nodes.get_mut(node) {
+            Some(entry) if from.contains(&entry.state) => {
+                entry.state = to;
+                Ok(Some(workflow.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn cancel_workflow(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut workflows = self.workflows.write().await;
+        Ok(match workflows.get_mut(&id) {
+            Some(workflow) => !std::mem::replace(&mut workflow.cancelled, true),
+            None => false,
+        })
+    }
+
+    async fn store_saga(&self, saga: &SagaRecord) -> Result<(), TaskError> {
+        let mut sagas = self.sagas.write().await;
+        sagas.insert(saga.id(), saga.clone());
+        Ok(())
+    }
+
+    async fn load_saga(&self, id: Uuid) -> Result<Option<SagaRecord>, TaskError> {
+        let sagas = self.sagas.read().await;
+        Ok(sagas.get(&id).cloned())
+    }
+
+    async fn load_schedule_last_run(&self, name: &str) -> Result<Option<DateTime<Utc>>, TaskError> {
+        let schedules = self.schedules.read().await;
+        Ok(schedules.get(name).copied())
+    }
+
+    async fn store_schedule_last_run(
+        &self,
+        name: &str,
+        last_run: DateTime<Utc>,
+    ) -> Result<(), TaskError> {
+        let mut schedules = self.schedules.write().await;
+        schedules.insert(name.to_string(), last_run);
+        Ok(())
+    }
 }
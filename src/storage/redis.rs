@@ -1,14 +1,41 @@
 use async_trait::async_trait;
-use redis::Client;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use redis::{Client, Script};
+use std::collections::HashMap;
 use uuid::Uuid;
 
-use crate::core::{Task, TaskError};
+use crate::core::{
+    DeadLetter, GroupRecord, NodeState, SagaRecord, Task, TaskError, TaskProgress, TaskStatus,
+    WorkflowRecord,
+};
 
 use super::Storage;
 
+/// Sets a node's state if it is one of the expected ones, returning all node
+/// states after the change. Refuses if the key passed as `KEYS[2]`, if any,
+/// exists.
+const TRANSITION_SCRIPT: &str = r"
+if KEYS[2] and redis.call('EXISTS', KEYS[2]) == 1 then
+    return false
+end
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+if not current then
+    return false
+end
+for i = 3, #ARGV do
+    if current == ARGV[i] then
+        redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+        return redis.call('HGETALL', KEYS[1])
+    end
+end
+return false
+";
+
 pub struct RedisStorage {
     client: Client,
     prefix: String,
+    transition_script: Script,
 }
 
 impl RedisStorage {
@@ -16,13 +43,120 @@ impl RedisStorage {
         Ok(Self {
             client: Client::open(redis_url)?,
             prefix: prefix.to_string(),
+            transition_script: Script::new(TRANSITION_SCRIPT),
         })
     }
-}
 
-#[async_trait]
-impl Storage for RedisStorage {
-    async fn store_task(&self, task: &Task) -> Result<(), TaskError> {
+    fn workflows_key(&self) -> String {
+        format!("{}:workflows", self.prefix)
+    }
+
+    /// Node states live apart from the workflow so they can change atomically.
+    fn workflow_nodes_key(&self, id: Uuid) -> String {
+        format!("{}:workflow:{}:nodes", self.prefix, id)
+    }
+
+    /// Set once a workflow is cancelled, which keeps its nodes from being
+    /// enqueued.
+    fn workflow_cancelled_key(&self, id: Uuid) -> String {
+        format!("{}:workflow:{}:cancelled", self.prefix, id)
+    }
+
+    /// Applies the stored node `states` to the workflow definition.
+    async fn merge_workflow(
+        &self,
+        conn: &mut redis::aio::Connection,
+        id: Uuid,
+        states: HashMap<String, String>,
+    ) -> Result<Option<WorkflowRecord>, TaskError> {
+        let workflow: Option<String> = redis::cmd("HGET")
+            .arg(self.workflows_key())
+            .arg(id.to_string())
+            .query_async(conn)
+            .await?;
+        let Some(workflow) = workflow else {
+            return Ok(None);
+        };
+        let mut workflow: WorkflowRecord = serde_json::from_str(&workflow)?;
+        workflow.cancelled = redis::cmd("EXISTS")
+            .arg(self.workflow_cancelled_key(id))
+            .query_async(conn)
+            .await?;
+        for (key, state) in states {
+            if let Some(node) = workflow.nodes.get_mut(&key) {
+                node.state = serde_json::from_str(&state)?;
+            }
+        }
+        Ok(Some(workflow))
+    }
+
+    fn sagas_key(&self) -> String {
+        format!("{}:sagas", self.prefix)
+    }
+
+    fn revoked_key(&self) -> String {
+        format!("{}:revoked", self.prefix)
+    }
+
+    fn dead_letters_key(&self) -> String {
+        format!("{}:dead_letters", self.prefix)
+    }
+
+    fn groups_key(&self) -> String {
+        format!("{}:groups", self.prefix)
+    }
+
+    fn group_pending_key(&self, id: Uuid) -> String {
+        format!("{}:group:{}:pending", self.prefix, id)
+    }
+
+    fn children_key(&self, parent_id: Uuid) -> String {
+        format!("{}:children:{}", self.prefix, parent_id)
+    }
+
+    fn completed_channel(&self, id: Uuid) -> String {
+        format!("{}:completed:{}", self.prefix, id)
+    }
+
+    /// Applies `change` to the stored task unless it is gone or `change`
+    /// returns `false`, retrying if the task is written meanwhile so that no
+    /// other change is lost. Returns the task as written.
+    async fn modify_task(
+        &self,
+        id: Uuid,
+        change: impl Fn(&mut Task) -> bool,
+    ) -> Result<Option<Task>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let key = format!("{}:{}", self.prefix, id);
+        loop {
+            let _: () = redis::cmd("WATCH").arg(&key).query_async(&mut conn).await?;
+            let task_json: Option<String> =
+                redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+            let mut task = match task_json {
+                Some(task_json) => serde_json::from_str::<Task>(&task_json)?,
+                None => {
+                    let _: () = redis::cmd("UNWATCH").query_async(&mut conn).await?;
+                    return Ok(None);
+                }
+            };
+            if !change(&mut task) {
+                let _: () = redis::cmd("UNWATCH").query_async(&mut conn).await?;
+                return Ok(None);
+            }
+            // EXEC returns nil if the task was written since WATCH.
+            let written: Option<()> = redis::pipe()
+                .atomic()
+                .set(&key, serde_json::to_string(&task)?)
+                .ignore()
+                .query_async(&mut conn)
+                .await?;
+            if written.is_some() {
+                return Ok(Some(task));
+            }
+        }
+    }
+
+    async fn write_task(&self, task: &Task) -> Result<(), TaskError> {
         let mut conn = self.client.get_async_connection().await?;
         let key = format!("{}:{}", self.prefix, task.id);
         let _: String = redis::cmd("SET")
@@ -30,6 +164,35 @@ impl Storage for RedisStorage {
             .arg(serde_json::to_string(task)?)
             .query_async(&mut conn)
             .await?;
+
+        if task.is_finished() {
+            let _: i64 = redis::cmd("SREM")
+                .arg(self.revoked_key())
+                .arg(task.id.to_string())
+                .query_async(&mut conn)
+                .await?;
+            let _: i64 = redis::cmd("PUBLISH")
+                .arg(self.completed_channel(task.id))
+                .arg(task.id.to_string())
+                .query_async(&mut conn)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Storage for RedisStorage {
+    async fn store_task(&self, task: &Task) -> Result<(), TaskError> {
+        self.write_task(task).await?;
+        if let Some(parent_id) = task.parent_id() {
+            let mut conn = self.client.get_async_connection().await?;
+            let _: i64 = redis::cmd("SADD")
+                .arg(self.children_key(parent_id))
+                .arg(task.id.to_string())
+                .query_async(&mut conn)
+                .await?;
+        }
         Ok(())
     }
 
@@ -46,23 +209,54 @@ impl Storage for RedisStorage {
     }
 
     async fn update_task(&self, task: &Task) -> Result<(), TaskError> {
-        let mut conn = self.client.get_async_connection().await?;
-        let key = format!("{}:{}", self.prefix, task.id);
-        let _: String = redis::cmd("SET")
-            .arg(&key)
-            .arg(serde_json::to_string(task)?)
-            .query_async(&mut conn)
-            .await?;
-        Ok(())
+        self.write_task(task).await
     }
 
     async fn delete_task(&self, id: Uuid) -> Result<(), TaskError> {
         let mut conn = self.client.get_async_connection().await?;
         let key = format!("{}:{}", self.prefix, id);
         let _: i64 = redis::cmd("DEL").arg(&key).query_async(&mut conn).await?;
+        let _: i64 = redis::cmd("SREM")
+            .arg(self.revoked_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn touch_heartbeat(&self, id: Uuid, at: DateTime<Utc>) -> Result<(), TaskError> {
+        self.modify_task(id, |task| {
+            task.heartbeat_at = Some(at);
+            true
+        })
+        .await?;
         Ok(())
     }
 
+    async fn store_progress(&self, id: Uuid, progress: &TaskProgress) -> Result<(), TaskError> {
+        self.modify_task(id, |task| {
+            task.progress = Some(progress.clone());
+            true
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn reclaim_task(
+        &self,
+        id: Uuid,
+        stale_before: DateTime<Utc>,
+    ) -> Result<Option<Task>, TaskError> {
+        self.modify_task(id, |task| {
+            let stale = task.is_stale(stale_before);
+            if stale {
+                task.set_status(TaskStatus::Pending);
+            }
+            stale
+        })
+        .await
+    }
+
     async fn list_tasks(&self) -> Result<Vec<Task>, TaskError> {
         let mut conn = self.client.get_async_connection().await?;
         let keys: Vec<String> = redis::cmd("KEYS")
@@ -70,11 +264,309 @@ impl Storage for RedisStorage {
             .query_async(&mut conn)
             .await?;
         let mut tasks = Vec::new();
-        for key in keys {
+        // Other records share the prefix; task keys end in a bare task id.
+        let task_keys = keys.into_iter().filter(|key| {
+            key.strip_prefix(&format!("{}:", self.prefix))
+                .is_some_and(|id| Uuid::parse_str(id).is_ok())
+        });
+        for key in task_keys {
             let task_json: String = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
             let task: Task = serde_json::from_str(&task_json)?;
             tasks.push(task);
         }
         Ok(tasks)
     }
+
+    async fn list_children(&self, parent_id: Uuid) -> Result<Vec<Task>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(self.children_key(parent_id))
+            .query_async(&mut conn)
+            .await?;
+        let mut children = Vec::with_capacity(ids.len());
+        for id in ids {
+            let task_json: Option<String> = redis::cmd("GET")
+                .arg(format!("{}:{}", self.prefix, id))
+                .query_async(&mut conn)
+                .await?;
+            if let Some(task_json) = task_json {
+                children.push(serde_json::from_str::<Task>(&task_json)?);
+            }
+        }
+        children.sort_by_key(Task::created_at);
+        Ok(children)
+    }
+
+    async fn wait_for_completion(&self, id: Uuid) -> Result<Task, TaskError> {
+        // Subscribe before checking so a completion in between is not missed.
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(self.completed_channel(id)).await?;
+
+        loop {
+            if let Some(task) = self.load_task(id).await? {
+                if task.is_finished() {
+                    return Ok(task);
+                }
+            }
+            if pubsub.on_message().next().await.is_none() {
+                return Err(TaskError::Other(format!(
+                    "Completion subscription for task {} closed",
+                    id
+                )));
+            }
+        }
+    }
+
+    async fn revoke_task(&self, id: Uuid) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: i64 = redis::cmd("SADD")
+            .arg(self.revoked_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        // A task finishing concurrently either sees the mark and drops it,
+        // or is seen finished here.
+        if self
+            .load_task(id)
+            .await?
+            .is_some_and(|task| task.is_finished())
+        {
+            let _: i64 = redis::cmd("SREM")
+                .arg(self.revoked_key())
+                .arg(id.to_string())
+                .query_async(&mut conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn is_revoked(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let revoked: bool = redis::cmd("SISMEMBER")
+            .arg(self.revoked_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(revoked)
+    }
+
+    async fn store_dead_letter(&self, letter: &DeadLetter) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: i64 = redis::cmd("HSET")
+            .arg(self.dead_letters_key())
+            .arg(letter.id().to_string())
+            .arg(serde_json::to_string(letter)?)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_dead_letter(&self, id: Uuid) -> Result<Option<DeadLetter>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let letter: Option<String> = redis::cmd("HGET")
+            .arg(self.dead_letters_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(letter
+            .map(|letter| serde_json::from_str(&letter))
+            .transpose()?)
+    }
+
+    async fn list_dead_letters(&self) -> Result<Vec<DeadLetter>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let letters: Vec<String> = redis::cmd("HVALS")
+            .arg(self.dead_letters_key())
+            .query_async(&mut conn)
+            .await?;
+        letters
+            .iter()
+            .map(|letter| Ok(serde_json::from_str(letter)?))
+            .collect()
+    }
+
+    async fn delete_dead_letter(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let removed: i64 = redis::cmd("HDEL")
+            .arg(self.dead_letters_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(removed == 1)
+    }
+
+    async fn store_group(&self, group: &GroupRecord) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().hset(
+            self.groups_key(),
+            group.id().to_string(),
+            serde_json::to_string(group)?,
+        );
+        if !group.members().is_empty() {
+            let members: Vec<String> = group.members().iter().map(Uuid::to_string).collect();
+            pipe.sadd(self.group_pending_key(group.id()), members);
+        }
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn load_group(&self, id: Uuid) -> Result<Option<GroupRecord>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let group: Option<String> = redis::cmd("HGET")
+            .arg(self.groups_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(group
+            .map(|group| serde_json::from_str(&group))
+            .transpose()?)
+    }
+
+    async fn update_group(&self, group: &GroupRecord) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: i64 = redis::cmd("HSET")
+            .arg(self.groups_key())
+            .arg(group.id().to_string())
+            .arg(serde_json::to_string(group)?)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn finish_group_member(&self, group_id: Uuid, task_id: Uuid) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let key = self.group_pending_key(group_id);
+        let (removed, pending): (i64, i64) = redis::pipe()
+            .atomic()
+            .srem(&key, task_id.to_string())
+            .scard(&key)
+            .query_async(&mut conn)
+            .await?;
+        Ok(removed == 1 && pending == 0)
+    }
+
+    async fn store_workflow(&self, workflow: &WorkflowRecord) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let states = workflow
+            .nodes()
+            .iter()
+            .map(|(key, node)| Ok((key.clone(), serde_json::to_string(&node.state())?)))
+            .collect::<Result<Vec<_>, TaskError>>()?;
+        let _: () = redis::pipe()
+            .atomic()
+            .hset(
+                self.workflows_key(),
+                workflow.id().to_string(),
+                serde_json::to_string(workflow)?,
+            )
+            .hset_multiple(self.workflow_nodes_key(workflow.id()), &states)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_workflow(&self, id: Uuid) -> Result<Option<WorkflowRecord>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let states: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(self.workflow_nodes_key(id))
+            .query_async(&mut conn)
+            .await?;
+        self.merge_workflow(&mut conn, id, states).await
+    }
+
+    async fn transition_workflow_node(
+        &self,
+        id: Uuid,
+        node: &str,
+        from: &[NodeState],
+        to: NodeState,
+    ) -> Result<Option<WorkflowRecord>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let mut invocation = self.transition_script.key(self.workflow_nodes_key(id));
+        if to == NodeState::Enqueued {
+            invocation.key(self.workflow_cancelled_key(id));
+        }
+        invocation.arg(node).arg(serde_json::to_string(&to)?);
+        for state in from {
+            invocation.arg(serde_json::to_string(state)?);
+        }
+        let states: Option<HashMap<String, String>> = invocation.invoke_async(&mut conn).await?;
+        match states {
+            Some(states) => self.merge_workflow(&mut conn, id, states).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn cancel_workflow(&self, id: Uuid) -> Result<bool, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let exists: bool = redis::cmd("HEXISTS")
+            .arg(self.workflows_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        if !exists {
+            return Ok(false);
+        }
+        let set: Option<String> = redis::cmd("SET")
+            .arg(self.workflow_cancelled_key(id))
+            .arg(1)
+            .arg("NX")
+            .query_async(&mut conn)
+            .await?;
+        Ok(set.is_some())
+    }
+
+    async fn store_saga(&self, saga: &SagaRecord) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: i64 = redis::cmd("HSET")
+            .arg(self.sagas_key())
+            .arg(saga.id().to_string())
+            .arg(serde_json::to_string(saga)?)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_saga(&self, id: Uuid) -> Result<Option<SagaRecord>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let saga: Option<String> = redis::cmd("HGET")
+            .arg(self.sagas_key())
+            .arg(id.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(saga.map(|saga| serde_json::from_str(&saga)).transpose()?)
+    }
+
+    async fn load_schedule_last_run(&self, name: &str) -> Result<Option<DateTime<Utc>>, TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let last_run: Option<String> = redis::cmd("HGET")
+            .arg(format!("{}:schedules", self.prefix))
+            .arg(name)
+            .query_async(&mut conn)
+            .await?;
+
+        last_run
+            .map(|last_run| {
+                DateTime::parse_from_rfc3339(&last_run)
+                    .map(|last_run| last_run.with_timezone(&Utc))
+                    .map_err(|e| TaskError::Other(e.to_string()))
+            })
+            .transpose()
+    }
+
+    async fn store_schedule_last_run(
+        &self,
+        name: &str,
+        last_run: DateTime<Utc>,
+    ) -> Result<(), TaskError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let _: i64 = redis::cmd("HSET")
+            .arg(format!("{}:schedules", self.prefix))
+            .arg(name)
+            .arg(last_run.to_rfc3339())
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
 }
@@ -1,13 +1,112 @@
 // src/storage/traits.rs
-use crate::core::{Task, TaskError};
+use crate::core::{
+    DeadLetter, GroupRecord, NodeState, SagaRecord, Task, TaskError, TaskProgress, WorkflowRecord,
+};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
 use uuid::Uuid;
 
+const COMPLETION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 #[async_trait]
 pub trait Storage: Send + Sync {
     async fn store_task(&self, task: &Task) -> Result<(), TaskError>;
     async fn load_task(&self, id: Uuid) -> Result<Option<Task>, TaskError>;
     async fn update_task(&self, task: &Task) -> Result<(), TaskError>;
     async fn delete_task(&self, id: Uuid) -> Result<(), TaskError>;
+    /// Records a running task's heartbeat without touching the rest of the
+    /// stored task, which others may be changing meanwhile.
+    async fn touch_heartbeat(&self, id: Uuid, at: DateTime<Utc>) -> Result<(), TaskError>;
+    /// Records a running task's progress without touching the rest of the
+    /// stored task.
+    async fn store_progress(&self, id: Uuid, progress: &TaskProgress) -> Result<(), TaskError>;
+    /// Moves a running task back to `Pending` if its last heartbeat, or its
+    /// creation if it has none, is before `stale_before`, atomically. Returns
+    /// the task as stored, or `None` if it was no longer running or was heard
+    /// from since.
+    async fn reclaim_task(
+        &self,
+        id: Uuid,
+        stale_before: DateTime<Utc>,
+    ) -> Result<Option<Task>, TaskError>;
     async fn list_tasks(&self) -> Result<Vec<Task>, TaskError>;
+
+    /// Tasks enqueued by the handler of `parent_id`, oldest first.
+    async fn list_children(&self, parent_id: Uuid) -> Result<Vec<Task>, TaskError> {
+        let mut children: Vec<Task> = self
+            .list_tasks()
+            .await?
+            .into_iter()
+            .filter(|task| task.parent_id() == Some(parent_id))
+            .collect();
+        children.sort_by_key(Task::created_at);
+        Ok(children)
+    }
+
+    /// Resolves once the task reaches a finished state. Backends override the
+    /// default polling with change notifications.
+    async fn wait_for_completion(&self, id: Uuid) -> Result<Task, TaskError> {
+        loop {
+            if let Some(task) = self.load_task(id).await? {
+                if task.is_finished() {
+                    return Ok(task);
+                }
+            }
+            tokio::time::sleep(COMPLETION_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Marks a task as revoked so that workers cancel it instead of running
+    /// it, or stop it if it is already running. The mark is dropped once the
+    /// task is stored in a finished state or deleted, and is not kept for a
+    /// task that has already finished.
+    async fn revoke_task(&self, id: Uuid) -> Result<(), TaskError>;
+    async fn is_revoked(&self, id: Uuid) -> Result<bool, TaskError>;
+
+    /// Keeps a copy of a failed task among the dead letters; the task itself
+    /// is left as it is.
+    async fn store_dead_letter(&self, letter: &DeadLetter) -> Result<(), TaskError>;
+    async fn load_dead_letter(&self, id: Uuid) -> Result<Option<DeadLetter>, TaskError>;
+    async fn list_dead_letters(&self) -> Result<Vec<DeadLetter>, TaskError>;
+    /// Returns whether the dead letter existed.
+    async fn delete_dead_letter(&self, id: Uuid) -> Result<bool, TaskError>;
+
+    /// Stores a group with all of its members pending.
+    async fn store_group(&self, group: &GroupRecord) -> Result<(), TaskError>;
+    async fn load_group(&self, id: Uuid) -> Result<Option<GroupRecord>, TaskError>;
+    /// Replaces a stored group's record, leaving its pending members as they
+    /// are.
+    async fn update_group(&self, group: &GroupRecord) -> Result<(), TaskError>;
+    /// Marks a member of a group as finished. Returns `true` only for the
+    /// call that finishes the last pending member.
+    async fn finish_group_member(&self, group_id: Uuid, task_id: Uuid) -> Result<bool, TaskError>;
+
+    async fn store_workflow(&self, workflow: &WorkflowRecord) -> Result<(), TaskError>;
+    async fn load_workflow(&self, id: Uuid) -> Result<Option<WorkflowRecord>, TaskError>;
+    /// Moves a node to `to` if it is in one of the `from` states. Returns the
+    /// workflow as it is right after the change, or `None` if the node was
+    /// in another state or does not exist. Nodes of a cancelled workflow
+    /// never move to `Enqueued`.
+    async fn transition_workflow_node(
+        &self,
+        id: Uuid,
+        node: &str,
+        from: &[NodeState],
+        to: NodeState,
+    ) -> Result<Option<WorkflowRecord>, TaskError>;
+    /// Marks a workflow as cancelled. Returns `false` if it already was or
+    /// does not exist.
+    async fn cancel_workflow(&self, id: Uuid) -> Result<bool, TaskError>;
+
+    /// Stores a saga, replacing the previous version of it.
+    async fn store_saga(&self, saga: &SagaRecord) -> Result<(), TaskError>;
+    async fn load_saga(&self, id: Uuid) -> Result<Option<SagaRecord>, TaskError>;
+
+    async fn load_schedule_last_run(&self, name: &str) -> Result<Option<DateTime<Utc>>, TaskError>;
+    async fn store_schedule_last_run(
+        &self,
+        name: &str,
+        last_run: DateTime<Utc>,
+    ) -> Result<(), TaskError>;
 }
@@ -1,18 +1,45 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
+use futures_util::Stream;
 use uuid::Uuid;
 
 use crate::broker::memory::MemoryBroker;
 use crate::broker::traits::Broker;
-use crate::core::{Task, TaskError, TaskSignature};
+use crate::core::{
+    Chain, Chord, DeadLetter, EnqueueOptions, Group, GroupRecord, Saga, SagaRecord, SagaStatus,
+    Task, TaskError, TaskSignature, TaskTree, Workflow, WorkflowRecord, WorkflowStatus,
+};
+use crate::events::{EventBus, EventPublisher, TaskEvent};
+use crate::handle::TaskHandle;
+use crate::lock::leader::LeaderElector;
+use crate::lock::traits::Lock;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::producer::{EnqueueMiddleware, Producer};
+use crate::scheduler::{PeriodicTask, Scheduler};
 use crate::storage::{MemoryStorage, Storage};
+use crate::worker::middleware::Middleware;
 use crate::worker::pool::WorkerPool;
-use crate::worker::registry::{TaskHandler, TaskRegistry};
+use crate::worker::reaper::{Reaper, ReaperConfig};
+use crate::worker::registry::{HandlerOptions, TaskHandler, TaskRegistry};
+
+const SCHEDULER_LEADER_KEY: &str = "scheduler-leader";
 
 pub struct TaskManagerBuilder {
     broker: Option<Arc<dyn Broker>>,
     storage: Option<Arc<dyn Storage>>,
     registry: Option<Arc<TaskRegistry>>,
+    reaper: Option<ReaperConfig>,
+    periodic_tasks: Vec<PeriodicTask>,
+    leader_lock: Option<Arc<dyn Lock>>,
+    events: EventBus,
+    cancel_grace_period: Option<Duration>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    enqueue_middlewares: Vec<Arc<dyn EnqueueMiddleware>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
     concurrency: usize,
 }
 
@@ -22,6 +49,15 @@ impl TaskManagerBuilder {
             broker: None,
             storage: None,
             registry: None,
+            reaper: None,
+            periodic_tasks: Vec::new(),
+            leader_lock: None,
+            events: EventBus::new(),
+            cancel_grace_period: None,
+            middlewares: Vec::new(),
+            enqueue_middlewares: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
             concurrency,
         }
     }
@@ -41,6 +77,63 @@ impl TaskManagerBuilder {
         self
     }
 
+    pub fn with_reaper(mut self, config: ReaperConfig) -> Self {
+        self.reaper = Some(config);
+        self
+    }
+
+    /// Runs `task` on its schedule while the manager is started.
+    pub fn with_periodic_task(mut self, task: PeriodicTask) -> Self {
+        self.periodic_tasks.push(task);
+        self
+    }
+
+    /// Elects a single scheduler leader through `lock` so that processes
+    /// sharing the same backends do not fire periodic tasks more than once.
+    pub fn with_leader_election<L: Lock + 'static>(mut self, lock: L) -> Self {
+        self.leader_lock = Some(Arc::new(lock));
+        self
+    }
+
+    /// Forwards every task lifecycle event to `publisher`, e.g. a
+    /// `RedisEventPublisher` so other processes can follow progress.
+    pub fn with_event_publisher<P: EventPublisher + 'static>(mut self, publisher: P) -> Self {
+        self.events = self.events.with_publisher(publisher);
+        self
+    }
+
+    /// Runs every task through `middleware`. Middlewares wrap each other in
+    /// the order they are added, the first being outermost.
+    pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Runs every new task through `middleware` before it reaches the
+    /// broker, including children enqueued by handlers.
+    pub fn with_enqueue_middleware<M: EnqueueMiddleware + 'static>(
+        mut self,
+        middleware: M,
+    ) -> Self {
+        self.enqueue_middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Records task and worker metrics of this process in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.events = self.events.with_publisher(metrics.clone());
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Aborts cancelled tasks that have not stopped on their own within
+    /// `grace_period`.
+    pub fn with_cancel_grace_period(mut self, grace_period: Duration) -> Self {
+        self.cancel_grace_period = Some(grace_period);
+        self
+    }
+
     pub fn build(self) -> TaskManager {
         let broker = self.broker.unwrap_or_else(|| Arc::new(MemoryBroker::new()));
         let storage = self
@@ -50,27 +143,67 @@ impl TaskManagerBuilder {
             .registry
             .unwrap_or_else(|| Arc::new(TaskRegistry::new()));
 
-        let pool = WorkerPool::new(
+        let producer = Producer::new(broker.clone(), storage.clone())
+            .with_events(self.events.clone())
+            .with_middlewares(Arc::new(self.enqueue_middlewares));
+
+        let mut pool = WorkerPool::new(
             broker.clone(),
             storage.clone(),
             registry.clone(),
             self.concurrency,
-        );
+        )
+        .with_events(self.events.clone())
+        .with_middlewares(Arc::new(self.middlewares))
+        .with_producer(producer.clone());
+        if let Some(grace_period) = self.cancel_grace_period {
+            pool = pool.with_cancel_grace_period(grace_period);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics {
+            pool = pool.with_metrics(metrics);
+        }
+
+        let reaper = self.reaper.map(|config| {
+            Reaper::new(broker.clone(), storage.clone(), config)
+                .with_events(self.events.clone())
+                .with_producer(producer.clone())
+        });
+
+        let scheduler = if self.periodic_tasks.is_empty() {
+            None
+        } else {
+            let mut scheduler = Scheduler::new(producer.clone(), storage.clone());
+            for task in self.periodic_tasks {
+                scheduler = scheduler.with_periodic_task(task);
+            }
+            if let Some(lock) = self.leader_lock {
+                scheduler =
+                    scheduler.with_leader_election(LeaderElector::new(lock, SCHEDULER_LEADER_KEY));
+            }
+            Some(scheduler)
+        };
 
         TaskManager {
-            broker,
+            producer,
             storage,
             registry,
             pool,
+            reaper,
+            scheduler,
+            events: self.events,
         }
     }
 }
 
 pub struct TaskManager {
-    broker: Arc<dyn Broker>,
+    producer: Producer,
     storage: Arc<dyn Storage>,
     registry: Arc<TaskRegistry>,
     pool: WorkerPool,
+    reaper: Option<Reaper>,
+    scheduler: Option<Scheduler>,
+    events: EventBus,
 }
 
 impl TaskManager {
@@ -79,10 +212,23 @@ impl TaskManager {
     }
 
     pub async fn start(&mut self) -> Result<(), TaskError> {
-        self.pool.start().await
+        self.pool.start().await?;
+        if let Some(reaper) = self.reaper.as_mut() {
+            reaper.start();
+        }
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.start();
+        }
+        Ok(())
     }
 
     pub async fn shutdown(&mut self) -> Result<(), TaskError> {
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.shutdown().await?;
+        }
+        if let Some(reaper) = self.reaper.as_mut() {
+            reaper.shutdown().await?;
+        }
         self.pool
             .shutdown()
             .await
@@ -96,24 +242,250 @@ impl TaskManager {
         self.registry.register(name, handler)
     }
 
+    pub fn register_handler_with_options<H>(
+        &self,
+        name: &str,
+        handler: H,
+        options: HandlerOptions,
+    ) -> Result<(), TaskError>
+    where
+        H: TaskHandler + 'static,
+    {
+        self.registry.register_with_options(name, handler, options)
+    }
+
     pub async fn enqueue_task(
         &self,
         signature: TaskSignature,
         max_retries: u32,
     ) -> Result<Uuid, TaskError> {
-        let payload = signature.to_bytes();
+        self.enqueue_task_with_options(
+            signature,
+            EnqueueOptions::new().with_max_retries(max_retries),
+        )
+        .await
+    }
+
+    pub async fn enqueue_task_with_options(
+        &self,
+        signature: TaskSignature,
+        options: EnqueueOptions,
+    ) -> Result<Uuid, TaskError> {
+        self.producer.enqueue(signature, options).await
+    }
+
+    /// Enqueues a task and returns a handle that resolves once it finishes.
+    pub async fn enqueue_task_with_handle(
+        &self,
+        signature: TaskSignature,
+        options: EnqueueOptions,
+    ) -> Result<TaskHandle, TaskError> {
+        let id = self.enqueue_task_with_options(signature, options).await?;
+        Ok(self.task_handle(id))
+    }
+
+    /// See [`Producer::enqueue_chain`].
+    pub async fn enqueue_chain(
+        &self,
+        chain: Chain,
+        options: EnqueueOptions,
+    ) -> Result<Vec<Uuid>, TaskError> {
+        self.producer.enqueue_chain(chain, options).await
+    }
+
+    /// See [`Producer::enqueue_group`].
+    pub async fn enqueue_group(
+        &self,
+        group: Group,
+        options: EnqueueOptions,
+    ) -> Result<GroupRecord, TaskError> {
+        self.producer.enqueue_group(group, options).await
+    }
+
+    /// See [`Producer::enqueue_chord`].
+    pub async fn enqueue_chord(
+        &self,
+        chord: Chord,
+        options: EnqueueOptions,
+    ) -> Result<GroupRecord, TaskError> {
+        self.producer.enqueue_chord(chord, options).await
+    }
+
+    pub async fn get_group(&self, id: Uuid) -> Result<Option<GroupRecord>, TaskError> {
+        self.storage.load_group(id).await
+    }
 
-        let task = Task::new(signature.name.to_string(), payload, max_retries);
-        self.broker.push(&task).await?;
+    /// See [`Producer::enqueue_workflow`].
+    pub async fn enqueue_workflow(
+        &self,
+        workflow: Workflow,
+        options: EnqueueOptions,
+    ) -> Result<WorkflowRecord, TaskError> {
+        self.producer.enqueue_workflow(workflow, options).await
+    }
+
+    pub async fn get_workflow(&self, id: Uuid) -> Result<Option<WorkflowRecord>, TaskError> {
+        self.storage.load_workflow(id).await
+    }
+
+    pub async fn workflow_status(&self, id: Uuid) -> Result<WorkflowStatus, TaskError> {
+        match self.storage.load_workflow(id).await? {
+            Some(workflow) => Ok(workflow.status()),
+            None => Err(TaskError::NotFound(id.to_string())),
+        }
+    }
+
+    /// See [`Producer::cancel_workflow`].
+    pub async fn cancel_workflow(&self, id: Uuid) -> Result<bool, TaskError> {
+        self.producer.cancel_workflow(id).await
+    }
+
+    /// Cancels a waiting or running task. See [`Producer::cancel`].
+    pub async fn cancel(&self, id: Uuid) -> Result<bool, TaskError> {
+        self.producer.cancel(id).await
+    }
+
+    /// See [`Producer::enqueue_saga`].
+    pub async fn enqueue_saga(
+        &self,
+        saga: Saga,
+        options: EnqueueOptions,
+    ) -> Result<SagaRecord, TaskError> {
+        self.producer.enqueue_saga(saga, options).await
+    }
+
+    pub async fn get_saga(&self, id: Uuid) -> Result<Option<SagaRecord>, TaskError> {
+        self.storage.load_saga(id).await
+    }
+
+    /// The saga's latest status, or `NotFound` if it does not exist.
+    pub async fn saga_status(&self, id: Uuid) -> Result<SagaStatus, TaskError> {
+        match self.storage.load_saga(id).await? {
+            Some(saga) => Ok(saga.status()),
+            None => Err(TaskError::NotFound(id.to_string())),
+        }
+    }
+
+    pub fn task_handle(&self, id: Uuid) -> TaskHandle {
+        TaskHandle::new(id, self.storage.clone())
+    }
+
+    pub fn producer(&self) -> &Producer {
+        &self.producer
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
 
-        Ok(task.id)
+    /// Lifecycle events of tasks handled by this manager, from now on.
+    pub fn subscribe_events(&self) -> impl Stream<Item = TaskEvent> + Send + 'static {
+        self.events.stream()
     }
 
     pub async fn list_tasks(&self) -> Result<Vec<Task>, TaskError> {
         self.storage.list_tasks().await
     }
 
+    /// Tasks enqueued by the handler of `id`, oldest first.
+    pub async fn get_children(&self, id: Uuid) -> Result<Vec<Task>, TaskError> {
+        self.storage.list_children(id).await
+    }
+
+    /// The task `id` with every task enqueued under it, or `None` if it
+    /// does not exist.
+    pub async fn task_tree(&self, id: Uuid) -> Result<Option<TaskTree>, TaskError> {
+        let Some(root) = self.storage.load_task(id).await? else {
+            return Ok(None);
+        };
+        let mut children = HashMap::new();
+        let mut parents = vec![id];
+        while let Some(parent) = parents.pop() {
+            let tasks = self.storage.list_children(parent).await?;
+            parents.extend(tasks.iter().map(Task::id));
+            children.insert(parent, tasks);
+        }
+        Ok(Some(TaskTree::assemble(root, &mut children)))
+    }
+
     pub async fn get_task(&self, id: uuid::Uuid) -> Result<Option<Task>, TaskError> {
         self.storage.load_task(id).await
     }
+
+    /// Copies of the tasks that exhausted their retries or failed
+    /// permanently. See [`DeadLetter`].
+    pub async fn list_dead_letters(&self) -> Result<Vec<DeadLetter>, TaskError> {
+        self.storage.list_dead_letters().await
+    }
+
+    pub async fn get_dead_letter(&self, id: Uuid) -> Result<Option<DeadLetter>, TaskError> {
+        self.storage.load_dead_letter(id).await
+    }
+
+    /// Deletes a dead task: its dead letter and the failed task record.
+    /// Returns whether the dead letter existed.
+    pub async fn purge_dead_letter(&self, id: Uuid) -> Result<bool, TaskError> {
+        if !self.storage.delete_dead_letter(id).await? {
+            return Ok(false);
+        }
+        self.storage.delete_task(id).await?;
+        Ok(true)
+    }
+
+    /// Deletes every dead task, returning how many there were.
+    pub async fn purge_dead_letters(&self) -> Result<usize, TaskError> {
+        let mut purged = 0;
+        for letter in self.storage.list_dead_letters().await? {
+            if self.purge_dead_letter(letter.id()).await? {
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Enqueues a dead task again with a fresh retry budget and removes it
+    /// from the dead letters.
+    pub async fn replay_dead_letter(&self, id: Uuid) -> Result<Uuid, TaskError> {
+        let letter = self.load_dead_letter_or_fail(id).await?;
+        self.replay(letter.task().clone()).await
+    }
+
+    /// Like [`TaskManager::replay_dead_letter`], running the task with
+    /// different arguments.
+    pub async fn replay_dead_letter_with_args(
+        &self,
+        id: Uuid,
+        args: Vec<serde_json::Value>,
+        kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Uuid, TaskError> {
+        let letter = self.load_dead_letter_or_fail(id).await?;
+        let mut task = letter.task().clone();
+        let mut signature: TaskSignature = serde_json::from_slice(task.payload())?;
+        signature.args = args;
+        signature.kwargs = kwargs;
+        task.set_payload(signature.to_bytes());
+        self.replay(task).await
+    }
+
+    /// Replays every dead letter, returning the replayed task ids.
+    pub async fn replay_dead_letters(&self) -> Result<Vec<Uuid>, TaskError> {
+        let mut replayed = Vec::new();
+        for letter in self.storage.list_dead_letters().await? {
+            replayed.push(self.replay(letter.task().clone()).await?);
+        }
+        Ok(replayed)
+    }
+
+    async fn load_dead_letter_or_fail(&self, id: Uuid) -> Result<DeadLetter, TaskError> {
+        self.storage
+            .load_dead_letter(id)
+            .await?
+            .ok_or_else(|| TaskError::NotFound(id.to_string()))
+    }
+
+    async fn replay(&self, task: Task) -> Result<Uuid, TaskError> {
+        let id = self.producer.replay(task).await?;
+        self.storage.delete_dead_letter(id).await?;
+        Ok(id)
+    }
 }
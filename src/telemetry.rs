@@ -0,0 +1,84 @@
+//! Tracing of tasks from the code that enqueued them to the worker that ran
+//! them.
+//!
+//! Every execution runs inside an `execute_task` span. With the
+//! `opentelemetry` feature, the trace context of the enqueueing span is also
+//! injected into the task's headers, and the execution span is made its
+//! child. Injection uses the global text map propagator, so install a W3C
+//! `TraceContextPropagator` to get `traceparent` headers.
+
+#[cfg(feature = "opentelemetry")]
+use std::collections::HashMap;
+
+#[cfg(feature = "opentelemetry")]
+use opentelemetry::propagation::{Extractor, Injector};
+#[cfg(feature = "opentelemetry")]
+use serde_json::Value;
+use tracing::{field, Span};
+#[cfg(feature = "opentelemetry")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::core::Task;
+use crate::events::TaskEventKind;
+
+/// Creates the span a task executes in.
+pub(crate) fn task_span(task: &Task) -> Span {
+    let span = tracing::info_span!(
+        "execute_task",
+        task.id = %task.id(),
+        task.name = %task.name(),
+        task.attempt = task.retries() + 1,
+        task.outcome = field::Empty,
+    );
+    #[cfg(feature = "opentelemetry")]
+    {
+        let _ = span.set_parent(extract_context(task.headers()));
+    }
+    span
+}
+
+/// Records how the execution running in the current span ended.
+pub(crate) fn record_outcome(kind: TaskEventKind) {
+    Span::current().record("task.outcome", field::debug(kind));
+}
+
+/// Writes the context of the current span into `headers`.
+#[cfg(feature = "opentelemetry")]
+pub fn inject_context(headers: &mut HashMap<String, Value>) {
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+/// Reads the context written by [`inject_context`] from `headers`.
+#[cfg(feature = "opentelemetry")]
+pub fn extract_context(headers: &HashMap<String, Value>) -> opentelemetry::Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    })
+}
+
+#[cfg(feature = "opentelemetry")]
+struct HeaderInjector<'a>(&'a mut HashMap<String, Value>);
+
+#[cfg(feature = "opentelemetry")]
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), Value::String(value));
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+struct HeaderExtractor<'a>(&'a HashMap<String, Value>);
+
+#[cfg(feature = "opentelemetry")]
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(Value::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+}
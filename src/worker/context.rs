@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::registry::HandlerOptions;
+use crate::core::{EnqueueOptions, Task, TaskError, TaskProgress, TaskSignature};
+use crate::producer::Producer;
+use crate::storage::Storage;
+
+/// The task a handler is running.
+#[derive(Clone)]
+pub struct TaskContext {
+    id: Uuid,
+    name: String,
+    parent_id: Option<Uuid>,
+    root_id: Option<Uuid>,
+    retries: u32,
+    max_retries: u32,
+    headers: HashMap<String, serde_json::Value>,
+    deadline: Option<DateTime<Utc>>,
+    pub(crate) soft_deadline: Option<Instant>,
+    pub(crate) hard_deadline: Option<Instant>,
+    cancellation: CancellationToken,
+    soft_time_limit: CancellationToken,
+    progress: ProgressReporter,
+    producer: Producer,
+    storage: Arc<dyn Storage>,
+}
+
+impl TaskContext {
+    pub(crate) fn new(
+        task: &Task,
+        options: &HandlerOptions,
+        progress: ProgressReporter,
+        producer: Producer,
+        storage: Arc<dyn Storage>,
+    ) -> Self {
+        let started = Instant::now();
+        let soft_time_limit = task.soft_time_limit().or(options.soft_time_limit());
+        let hard_time_limit = task.hard_time_limit().or(options.hard_time_limit());
+
+        Self {
+            id: task.id(),
+            name: task.name().to_string(),
+            parent_id: task.parent_id(),
+            root_id: task.root_id(),
+            retries: task.retries(),
+            max_retries: task.max_retries(),
+            headers: task.headers().clone(),
+            deadline: hard_time_limit.and_then(|limit| {
+                chrono::Duration::from_std(limit)
+                    .ok()
+                    .and_then(|limit| Utc::now().checked_add_signed(limit))
+            }),
+            soft_deadline: soft_time_limit.map(|limit| started + limit),
+            hard_deadline: hard_time_limit.map(|limit| started + limit),
+            cancellation: CancellationToken::new(),
+            soft_time_limit: CancellationToken::new(),
+            progress,
+            producer,
+            storage,
+        }
+    }
+
+    /// This context with the details of `task`, as changed by middlewares.
+    /// Cancellation, time limits and progress stay shared with `self`.
+    pub(crate) fn for_task(&self, task: &Task) -> Self {
+        Self {
+            name: task.name().to_string(),
+            parent_id: task.parent_id(),
+            root_id: task.root_id(),
+            retries: task.retries(),
+            max_retries: task.max_retries(),
+            headers: task.headers().clone(),
+            ..self.clone()
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The task whose handler enqueued this one.
+    pub fn parent_id(&self) -> Option<Uuid> {
+        self.parent_id
+    }
+
+    /// The task at the top of this one's tree; the task itself if it has no
+    /// parent.
+    pub fn root_id(&self) -> Uuid {
+        self.root_id.unwrap_or(self.id)
+    }
+
+    /// How many times the task has been retried before this attempt.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    pub fn headers(&self) -> &HashMap<String, serde_json::Value> {
+        &self.headers
+    }
+
+    /// When the hard time limit aborts the task, if it has one.
+    pub fn deadline(&self) -> Option<DateTime<Utc>> {
+        self.deadline
+    }
+
+    /// Triggered when the task is cancelled while it runs. Handlers should
+    /// stop at the next convenient point.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Resolves once the task is cancelled.
+    pub async fn cancelled(&self) {
+        self.cancellation.cancelled().await
+    }
+
+    /// Whether the task has run past its soft time limit and should wrap up
+    /// before the hard limit aborts it.
+    pub fn soft_time_limit_exceeded(&self) -> bool {
+        self.soft_time_limit.is_cancelled()
+    }
+
+    /// Resolves once the task runs past its soft time limit.
+    pub async fn soft_time_limit_reached(&self) {
+        self.soft_time_limit.cancelled().await
+    }
+
+    pub(crate) fn exceed_soft_time_limit(&self) {
+        self.soft_time_limit.cancel();
+    }
+
+    pub fn progress(&self) -> &ProgressReporter {
+        &self.progress
+    }
+
+    /// Enqueues a child of this task through the same broker and storage.
+    /// The child inherits this task's headers unless `options` sets them
+    /// itself. Return [`TaskError::Suspend`] to wait for the children
+    /// without holding a worker.
+    pub async fn enqueue(
+        &self,
+        signature: TaskSignature,
+        mut options: EnqueueOptions,
+    ) -> Result<Uuid, TaskError> {
+        for (key, value) in &self.headers {
+            options
+                .headers
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+        options.parent_id = Some(self.id);
+        options.root_id = Some(self.root_id());
+        self.producer.enqueue(signature, options).await
+    }
+
+    /// Children enqueued by this task so far, oldest first, including those
+    /// of earlier runs before it was suspended.
+    pub async fn children(&self) -> Result<Vec<Task>, TaskError> {
+        self.storage.list_children(self.id).await
+    }
+}
+
+/// Records a running task's progress on the task itself.
+///
+/// Reports are cheap: the executor persists only the latest one, at most
+/// once per progress interval, and once more when the task finishes.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    sender: Arc<watch::Sender<Option<TaskProgress>>>,
+}
+
+impl ProgressReporter {
+    pub(crate) fn channel() -> (Self, watch::Receiver<Option<TaskProgress>>) {
+        let (sender, receiver) = watch::channel(None);
+        let reporter = Self {
+            sender: Arc::new(sender),
+        };
+        (reporter, receiver)
+    }
+
+    pub fn report(&self, progress: TaskProgress) {
+        self.sender.send_replace(Some(progress));
+    }
+
+    pub fn update(&self, current: u64, total: u64) {
+        self.report(TaskProgress::new(current, total));
+    }
+
+    pub fn update_percentage(&self, percentage: f64) {
+        self.report(TaskProgress::from_percentage(percentage));
+    }
+
+    /// Like [`ProgressReporter::update`], attaching free-form state.
+    pub fn update_with_meta(&self, current: u64, total: u64, meta: serde_json::Value) {
+        self.report(TaskProgress::new(current, total).with_meta(meta));
+    }
+}
@@ -1,23 +1,45 @@
-use async_trait::async_trait;
+use chrono::Utc;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::{warn, Instrument};
 
 use crate::broker::traits::Broker;
-use crate::core::{Task, TaskError, TaskSignature, TaskStatus};
+use crate::core::{DeadLetter, Task, TaskError, TaskProgress, TaskStatus};
+use crate::events::{EventBus, TaskEvent, TaskEventKind};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::producer::Producer;
 use crate::storage::Storage;
+use crate::telemetry;
 
+use super::context::{ProgressReporter, TaskContext};
+pub use super::middleware::{Middleware, Next};
 use super::registry::{TaskHandler, TaskRegistry};
 
-#[async_trait]
-pub trait Middleware: Send + Sync {
-    async fn before_execution(&self, task: &Task) -> Result<(), TaskError>;
-    async fn after_execution(&self, task: &Task) -> Result<(), TaskError>;
-}
+/// How often a running task's heartbeat is written to storage and its
+/// reservation extended. Must be well below the broker's visibility timeout.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Minimum time between two progress writes of a running task.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// How often a running task is checked against the revocation list.
+pub const DEFAULT_REVOCATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct Executor {
     broker: Arc<dyn Broker>,
     storage: Arc<dyn Storage>,
     registry: Arc<TaskRegistry>,
-    middlewares: Vec<Box<dyn Middleware>>,
+    middlewares: Arc<Vec<Arc<dyn Middleware>>>,
+    heartbeat_interval: Duration,
+    revocation_check_interval: Duration,
+    progress_interval: Duration,
+    cancel_grace_period: Option<Duration>,
+    events: EventBus,
+    producer: Option<Producer>,
+    worker_id: Option<String>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
 }
 
 impl Executor {
@@ -30,56 +52,328 @@ impl Executor {
             broker,
             storage,
             registry,
-            middlewares: Vec::new(),
+            middlewares: Arc::new(Vec::new()),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            revocation_check_interval: DEFAULT_REVOCATION_CHECK_INTERVAL,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            cancel_grace_period: None,
+            events: EventBus::new(),
+            producer: None,
+            worker_id: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Producer handed to tasks for enqueueing children. Defaults to one
+    /// sharing the executor's broker, storage and events.
+    pub fn with_producer(mut self, producer: Producer) -> Self {
+        self.producer = Some(producer);
+        self
+    }
+
+    /// Records queue wait and execution times in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_worker_id(mut self, worker_id: String) -> Self {
+        self.worker_id = Some(worker_id);
+        self
+    }
+
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    pub fn with_revocation_check_interval(mut self, interval: Duration) -> Self {
+        self.revocation_check_interval = interval;
+        self
+    }
+
+    /// Throttles progress writes so chatty handlers do not flood storage.
+    pub fn with_progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Aborts a cancelled task that has not stopped on its own within
+    /// `grace_period`. Without it cancellation is purely cooperative.
+    pub fn with_cancel_grace_period(mut self, grace_period: Duration) -> Self {
+        self.cancel_grace_period = Some(grace_period);
+        self
+    }
+
     pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
-        self.middlewares.push(Box::new(middleware));
+        Arc::make_mut(&mut self.middlewares).push(Arc::new(middleware));
+    }
+
+    /// Replaces the middleware chain, outermost first.
+    pub fn with_middlewares(mut self, middlewares: Arc<Vec<Arc<dyn Middleware>>>) -> Self {
+        self.middlewares = middlewares;
+        self
     }
 
-    pub async fn execute_task(&self, mut task: Task) -> Result<(), TaskError> {
+    /// Runs a task reserved from the broker. The reservation is released
+    /// once the task's outcome is stored, and before it is pushed again.
+    pub async fn execute_task(&self, task: Task) -> Result<(), TaskError> {
+        let span = telemetry::task_span(&task);
+        self.execute(task).instrument(span).await
+    }
+
+    async fn execute(&self, mut task: Task) -> Result<(), TaskError> {
+        if self.storage.is_revoked(task.id()).await? {
+            return self.cancel(task).await;
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            let ready_at = task.eta().unwrap_or(task.created_at());
+            let wait = (Utc::now() - ready_at).to_std().unwrap_or_default();
+            metrics.observe_queue_wait(task.name(), wait);
+        }
+
         task.set_status(TaskStatus::Running);
+        task.heartbeat();
         self.storage.update_task(&task).await?;
-        let handler = match self.registry.get(task.name())? {
+        self.emit(&task, TaskEventKind::Started, None).await;
+        let (handler, options) = match self.registry.get_with_options(task.name())? {
             Some(h) => h,
-            None => return Err(TaskError::HandlerNotFound(task.name().to_string())),
+            None => {
+                let err = TaskError::HandlerNotFound(task.name().to_string());
+                return self.fail(task, err).await;
+            }
         };
 
-        let result = self.process_task(&task, handler.as_ref()).await;
+        let (progress, mut progress_rx) = ProgressReporter::channel();
+        let ctx = TaskContext::new(
+            &task,
+            &options,
+            progress,
+            self.producer(),
+            Arc::clone(&self.storage),
+        );
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let result = self
+            .run_with_heartbeat(&task, handler.as_ref(), &ctx, &mut progress_rx)
+            .await;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_execution(task.name(), started.elapsed());
+        }
+        if let Some(progress) = progress_rx.borrow().clone() {
+            task.set_progress(Some(progress));
+        }
+        if result.is_err() && ctx.is_cancelled() {
+            return self.cancel(task).await;
+        }
+
         match result {
             Ok(rs) => {
                 task.set_status(TaskStatus::Completed);
                 task.set_result(rs);
                 self.storage.update_task(&task).await?;
+                self.broker.ack(task.id()).await?;
+                self.emit(&task, TaskEventKind::Succeeded, None).await;
+                self.advance(&task).await;
                 Ok(())
             }
+            Err(TaskError::Ignore) => {
+                task.set_status(TaskStatus::Ignored);
+                self.storage.update_task(&task).await?;
+                self.broker.ack(task.id()).await?;
+                self.emit(&task, TaskEventKind::Ignored, None).await;
+                self.advance(&task).await;
+                Ok(())
+            }
+            Err(TaskError::Suspend) => {
+                task.set_status(TaskStatus::Waiting);
+                self.storage.update_task(&task).await?;
+                // Released first: the last child may push the task again.
+                self.broker.ack(task.id()).await?;
+                self.producer().suspend(&task).await
+            }
             Err(e) => {
-                if task.retries() < task.max_retries() {
-                    task.increment_retries();
-                    task.set_status(TaskStatus::Pending);
-                    self.broker.push(&task).await?;
-                    Ok(())
-                } else {
-                    task.set_status(TaskStatus::Failed(e.to_string()));
-                    self.storage.update_task(&task).await?;
-                    Err(e)
+                let policy = task.retry_policy().unwrap_or(options.retry_policy());
+                if !policy.is_retryable(&e) {
+                    return self.fail(task, e).await;
                 }
+                if task.retries() >= task.max_retries() {
+                    if let TaskError::Retry { .. } = e {
+                        return self.fail(task, TaskError::MaxRetriesExceeded).await;
+                    }
+                    return self.fail(task, e).await;
+                }
+
+                let delay = match e {
+                    TaskError::Retry { after: Some(after) } => after,
+                    _ => policy.delay(task.retries() + 1, task.retry_delay()),
+                };
+                task.increment_retries();
+                task.schedule_retry(delay);
+                task.set_status(if task.is_delayed(Utc::now()) {
+                    TaskStatus::Scheduled
+                } else {
+                    TaskStatus::Pending
+                });
+                self.storage.update_task(&task).await?;
+                // Released before the push, as another worker may reserve the
+                // task again right away.
+                self.broker.ack(task.id()).await?;
+                self.broker.push(&task).await?;
+                self.emit(&task, TaskEventKind::Retried, Some(e.to_string()))
+                    .await;
+                Ok(())
             }
         }
     }
 
-    async fn process_task(
+    async fn fail(&self, mut task: Task, e: TaskError) -> Result<(), TaskError> {
+        let (status, kind) = match e {
+            TaskError::TimedOut(_) => (TaskStatus::TimedOut, TaskEventKind::TimedOut),
+            _ => (TaskStatus::Failed(e.to_string()), TaskEventKind::Failed),
+        };
+        task.set_status(status);
+        self.storage.update_task(&task).await?;
+        self.storage
+            .store_dead_letter(&DeadLetter::new(task.clone(), e.to_string()))
+            .await?;
+        self.broker.ack(task.id()).await?;
+        self.emit(&task, kind, Some(e.to_string())).await;
+        self.advance(&task).await;
+        Err(e)
+    }
+
+    async fn cancel(&self, mut task: Task) -> Result<(), TaskError> {
+        task.set_status(TaskStatus::Cancelled);
+        self.storage.update_task(&task).await?;
+        self.broker.ack(task.id()).await?;
+        self.emit(&task, TaskEventKind::Cancelled, None).await;
+        self.advance(&task).await;
+        Ok(())
+    }
+
+    fn producer(&self) -> Producer {
+        self.producer.clone().unwrap_or_else(|| {
+            Producer::new(Arc::clone(&self.broker), Arc::clone(&self.storage))
+                .with_events(self.events.clone())
+        })
+    }
+
+    /// Moves the workflows of a finished task forward. The task's own
+    /// outcome stands even if that fails.
+    async fn advance(&self, task: &Task) {
+        if let Err(e) = self.producer().advance(task).await {
+            warn!("Failed to advance workflow of {}: {:?}", task, e);
+        }
+    }
+
+    async fn emit(&self, task: &Task, kind: TaskEventKind, reason: Option<String>) {
+        if kind != TaskEventKind::Started {
+            telemetry::record_outcome(kind);
+        }
+        let mut event = TaskEvent::new(task, kind).with_worker_id(self.worker_id.as_deref());
+        event.reason = reason;
+        self.events.emit(event).await;
+    }
+
+    async fn run_with_heartbeat(
         &self,
         task: &Task,
         handler: &dyn TaskHandler,
+        ctx: &TaskContext,
+        progress: &mut watch::Receiver<Option<TaskProgress>>,
     ) -> Result<Vec<u8>, TaskError> {
-        let signature: TaskSignature = serde_json::from_slice(task.payload())?;
+        let process = self.process_task(task, handler, ctx);
+        tokio::pin!(process);
 
-        if signature.name != task.name() {
-            return Err(TaskError::InvalidSignature);
+        let mut heartbeat = tokio::time::interval(self.heartbeat_interval);
+        heartbeat.tick().await;
+        let mut revocation = tokio::time::interval(self.revocation_check_interval);
+        revocation.tick().await;
+        let mut abort_at: Option<Instant> = None;
+        let mut progress_written: Option<Instant> = None;
+        let mut progress_due: Option<Instant> = None;
+
+        loop {
+            tokio::select! {
+                result = &mut process => return result,
+                _ = heartbeat.tick() => {
+                    if let Err(e) = self.storage.touch_heartbeat(task.id(), Utc::now()).await {
+                        warn!("Failed to record heartbeat for {}: {:?}", task, e);
+                    }
+                    match self.broker.extend(task.id()).await {
+                        Ok(true) => {}
+                        Ok(false) => warn!("{} is no longer reserved by this worker", task),
+                        Err(e) => warn!("Failed to extend reservation of {}: {:?}", task, e),
+                    }
+                }
+                // Further reports are picked up when the pending write is due.
+                Ok(()) = progress.changed(), if progress_due.is_none() => {
+                    let due = progress_written.map(|written| written + self.progress_interval);
+                    progress_due = Some(due.unwrap_or_else(Instant::now));
+                }
+                _ = sleep_until(progress_due) => {
+                    progress_due = None;
+                    progress_written = Some(Instant::now());
+                    let latest = progress.borrow_and_update().clone();
+                    if let Some(latest) = latest {
+                        if let Err(e) = self.storage.store_progress(task.id(), &latest).await {
+                            warn!("Failed to record progress for {}: {:?}", task, e);
+                        }
+                    }
+                }
+                _ = revocation.tick(), if !ctx.is_cancelled() => {
+                    match self.storage.is_revoked(task.id()).await {
+                        Ok(true) => {
+                            ctx.cancellation_token().cancel();
+                            abort_at = self.cancel_grace_period.map(|grace| Instant::now() + grace);
+                        }
+                        Ok(false) => {}
+                        Err(e) => warn!("Failed to check revocation of {}: {:?}", task, e),
+                    }
+                }
+                _ = sleep_until(ctx.soft_deadline), if !ctx.soft_time_limit_exceeded() => {
+                    warn!("{} exceeded its soft time limit", task);
+                    ctx.exceed_soft_time_limit();
+                }
+                // Dropping the handler future aborts it.
+                _ = sleep_until(ctx.hard_deadline) => {
+                    return Err(TaskError::TimedOut(task.id()));
+                }
+                _ = sleep_until(abort_at) => {
+                    return Err(TaskError::Cancelled(task.id()));
+                }
+            }
         }
+    }
+
+    async fn process_task(
+        &self,
+        task: &Task,
+        handler: &dyn TaskHandler,
+        ctx: &TaskContext,
+    ) -> Result<Vec<u8>, TaskError> {
+        // Middlewares may change the task for the rest of the chain only.
+        let mut task = task.clone();
+        Next::new(&self.middlewares, handler, ctx)
+            .run(&mut task)
+            .await
+    }
+}
 
-        handler.handle(signature.args, signature.kwargs).await
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
     }
 }
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::context::TaskContext;
+use super::registry::TaskHandler;
+use crate::core::{Task, TaskError, TaskSignature};
+
+/// Wraps the execution of every task run by a worker.
+///
+/// Middlewares form a chain around the handler: each one decides whether
+/// and how to call the rest of the chain through [`Next::run`], sees its
+/// result or error, and may change the task the rest of the chain receives.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    async fn handle(&self, task: &mut Task, next: Next<'_>) -> Result<Vec<u8>, TaskError> {
+        self.before_execution(task).await?;
+        let result = next.run(task).await;
+        self.after_execution(task).await?;
+        result
+    }
+
+    /// Runs before the rest of the chain unless `handle` is overridden. An
+    /// error skips the handler.
+    async fn before_execution(&self, _task: &Task) -> Result<(), TaskError> {
+        Ok(())
+    }
+
+    /// Runs after the rest of the chain unless `handle` is overridden.
+    async fn after_execution(&self, _task: &Task) -> Result<(), TaskError> {
+        Ok(())
+    }
+}
+
+/// The rest of a middleware chain, ending in the task's handler.
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    handler: &'a dyn TaskHandler,
+    ctx: &'a TaskContext,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middlewares: &'a [Arc<dyn Middleware>],
+        handler: &'a dyn TaskHandler,
+        ctx: &'a TaskContext,
+    ) -> Self {
+        Self {
+            middlewares,
+            handler,
+            ctx,
+        }
+    }
+
+    /// The context of the task as it was reserved. The handler gets one
+    /// built from the task the chain passes on, so it sees middleware
+    /// changes such as new headers.
+    pub fn context(&self) -> &TaskContext {
+        self.ctx
+    }
+
+    pub async fn run(self, task: &mut Task) -> Result<Vec<u8>, TaskError> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => {
+                let next = Next {
+                    middlewares: rest,
+                    ..self
+                };
+                middleware.handle(task, next).await
+            }
+            None => {
+                let signature: TaskSignature = serde_json::from_slice(task.payload())?;
+                if signature.name != task.name() {
+                    return Err(TaskError::InvalidSignature);
+                }
+                let ctx = self.ctx.for_task(task);
+                self.handler
+                    .handle_with_context(&ctx, signature.args, signature.kwargs)
+                    .await
+            }
+        }
+    }
+}
@@ -1,3 +1,6 @@
+pub mod context;
 pub mod executor;
+pub mod middleware;
 pub mod pool;
+pub mod reaper;
 pub mod registry;
@@ -3,9 +3,17 @@ use std::{error::Error, sync::Arc, time::Duration};
 use tokio::{sync::broadcast, task::JoinHandle};
 use tracing::{error, info};
 
-use crate::{broker::traits::Broker, core::TaskError, storage::Storage};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::{
+    broker::traits::Broker, core::TaskError, events::EventBus, producer::Producer, storage::Storage,
+};
 
-use super::{executor::Executor, registry::TaskRegistry};
+use super::{executor::Executor, middleware::Middleware, registry::TaskRegistry};
+
+/// How often the queue depth gauge is refreshed when metrics are tracked.
+#[cfg(feature = "metrics")]
+pub const QUEUE_DEPTH_INTERVAL: Duration = Duration::from_secs(5);
 
 pub struct WorkerPool {
     workers: Vec<JoinHandle<()>>,
@@ -13,6 +21,12 @@ pub struct WorkerPool {
     storage: Arc<dyn Storage>,
     registry: Arc<TaskRegistry>,
     concurrency: usize,
+    cancel_grace_period: Option<Duration>,
+    middlewares: Arc<Vec<Arc<dyn Middleware>>>,
+    producer: Option<Producer>,
+    events: EventBus,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
     shutdown_tx: broadcast::Sender<()>,
 }
 
@@ -31,34 +45,112 @@ impl WorkerPool {
             storage,
             registry,
             concurrency,
+            cancel_grace_period: None,
+            middlewares: Arc::new(Vec::new()),
+            producer: None,
+            events: EventBus::new(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
             shutdown_tx,
         }
     }
 
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Runs every task through `middleware`, after the ones added before it.
+    pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        Arc::make_mut(&mut self.middlewares).push(Arc::new(middleware));
+        self
+    }
+
+    /// Replaces the middleware chain shared by all workers, outermost first.
+    pub fn with_middlewares(mut self, middlewares: Arc<Vec<Arc<dyn Middleware>>>) -> Self {
+        self.middlewares = middlewares;
+        self
+    }
+
+    /// See [`Executor::with_producer`].
+    pub fn with_producer(mut self, producer: Producer) -> Self {
+        self.producer = Some(producer);
+        self
+    }
+
+    /// Tracks busy workers, queue depth and task timings in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// See [`Executor::with_cancel_grace_period`].
+    pub fn with_cancel_grace_period(mut self, grace_period: Duration) -> Self {
+        self.cancel_grace_period = Some(grace_period);
+        self
+    }
+
     pub async fn start(&mut self) -> Result<(), TaskError> {
-        for _ in 0..self.concurrency {
+        for index in 0..self.concurrency {
             let worker = self.spawn_worker(
+                format!("worker-{}-{}", std::process::id(), index),
                 Arc::clone(&self.broker),
                 Arc::clone(&self.storage),
                 Arc::clone(&self.registry),
             );
             self.workers.push(worker);
         }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = self.metrics.clone() {
+            let sampler = self.spawn_queue_depth_sampler(metrics);
+            self.workers.push(sampler);
+        }
 
         Ok(())
     }
 
+    /// Refreshes the queue depth gauge every [`QUEUE_DEPTH_INTERVAL`], so
+    /// that workers do not ask the broker for it on every pop.
+    #[cfg(feature = "metrics")]
+    fn spawn_queue_depth_sampler(&self, metrics: Metrics) -> JoinHandle<()> {
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let broker = Arc::clone(&self.broker);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(QUEUE_DEPTH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = shutdown_rx.recv() => break,
+                    _ = interval.tick() => match broker.queue_length().await {
+                        Ok(depth) => metrics.set_queue_depth(depth),
+                        Err(e) => error!("Failed to read queue depth: {:?}", e),
+                    },
+                }
+            }
+        })
+    }
+
     fn spawn_worker(
         &self,
+        worker_id: String,
         broker: Arc<dyn Broker>,
         storage: Arc<dyn Storage>,
         registry: Arc<TaskRegistry>,
     ) -> JoinHandle<()> {
         let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let events = self.events.clone();
+        let cancel_grace_period = self.cancel_grace_period;
+        let middlewares = Arc::clone(&self.middlewares);
+        let producer = self.producer.clone();
+        #[cfg(feature = "metrics")]
+        let metrics = self.metrics.clone();
 
         tokio::spawn(async move {
             loop {
                 tokio::select! {
+                    // Check for shutdown first; an empty queue is always ready.
+                    biased;
                     Ok(_) = shutdown_rx.recv() => {
                         info!("Worker is shutting down");
                         break;
@@ -66,15 +158,37 @@ impl WorkerPool {
                     task = broker.pop() => {
                         match task {
                             Ok(Some(task)) => {
-                                let executor = Executor::new(
+                                let id = task.id();
+                                let mut executor = Executor::new(
                                     Arc::clone(&broker),
                                     Arc::clone(&storage),
                                     Arc::clone(&registry),
-                                );
+                                )
+                                .with_events(events.clone())
+                                .with_worker_id(worker_id.clone())
+                                .with_middlewares(Arc::clone(&middlewares));
+                                if let Some(grace_period) = cancel_grace_period {
+                                    executor = executor.with_cancel_grace_period(grace_period);
+                                }
+                                if let Some(producer) = &producer {
+                                    executor = executor.with_producer(producer.clone());
+                                }
+                                #[cfg(feature = "metrics")]
+                                if let Some(metrics) = &metrics {
+                                    executor = executor.with_metrics(metrics.clone());
+                                    metrics.worker_busy();
+                                }
 
-                                let result = executor.execute_task(task).await;
-                                if let Err(e) = result {
-                                    error!("Failed to execute task: {:?}", e);
+                                let outcome = executor.execute_task(task).await;
+                                #[cfg(feature = "metrics")]
+                                if let Some(metrics) = &metrics {
+                                    metrics.worker_idle();
+                                }
+                                // The executor releases the reservation once the outcome
+                                // is stored; on errors before that the task stays
+                                // reserved and the broker redelivers it.
+                                if let Err(e) = outcome {
+                                    error!("Failed to execute task {}: {:?}", id, e);
                                 }
                             }
                             Ok(None) => {
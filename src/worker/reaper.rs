@@ -0,0 +1,186 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::{sync::broadcast, task::JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::{
+    broker::traits::Broker,
+    core::{DeadLetter, TaskError, TaskStatus},
+    events::{EventBus, TaskEvent, TaskEventKind},
+    producer::Producer,
+    storage::Storage,
+};
+
+#[derive(Debug, Clone)]
+pub struct ReaperConfig {
+    /// How often storage is scanned for abandoned tasks.
+    pub interval: Duration,
+    /// How long a running task may go without a heartbeat before it is
+    /// considered abandoned. Must be comfortably larger than the executor's
+    /// heartbeat interval, and shorter than the broker's visibility timeout
+    /// for the reaper to count the lost run before the broker redelivers it.
+    pub lease_timeout: Duration,
+}
+
+impl Default for ReaperConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            lease_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Recovers tasks left in `Running` by workers that died mid-execution.
+pub struct Reaper {
+    broker: Arc<dyn Broker>,
+    storage: Arc<dyn Storage>,
+    config: ReaperConfig,
+    events: EventBus,
+    producer: Option<Producer>,
+    handle: Option<JoinHandle<()>>,
+    shutdown_tx: broadcast::Sender<()>,
+}
+
+impl Reaper {
+    pub fn new(broker: Arc<dyn Broker>, storage: Arc<dyn Storage>, config: ReaperConfig) -> Self {
+        let (shutdown_tx, _) = broadcast::channel(1);
+
+        Self {
+            broker,
+            storage,
+            config,
+            events: EventBus::new(),
+            producer: None,
+            handle: None,
+            shutdown_tx,
+        }
+    }
+
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Producer used to move the workflows of failed tasks forward.
+    pub fn with_producer(mut self, producer: Producer) -> Self {
+        self.producer = Some(producer);
+        self
+    }
+
+    pub fn start(&mut self) {
+        let broker = Arc::clone(&self.broker);
+        let storage = Arc::clone(&self.storage);
+        let config = self.config.clone();
+        let events = self.events.clone();
+        let producer = self.producer.clone();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        self.handle = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.interval);
+            loop {
+                tokio::select! {
+                    Ok(_) = shutdown_rx.recv() => {
+                        info!("Reaper is shutting down");
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let reaped = reap_expired(
+                            broker.as_ref(),
+                            storage.as_ref(),
+                            &events,
+                            producer.as_ref(),
+                            config.lease_timeout,
+                        )
+                        .await;
+                        if let Err(e) = reaped {
+                            error!("Failed to reap expired tasks: {:?}", e);
+                        }
+                    }
+                }
+            }
+        }));
+    }
+
+    /// Runs a single scan, returning how many tasks were recovered.
+    pub async fn reap(&self) -> Result<usize, TaskError> {
+        reap_expired(
+            self.broker.as_ref(),
+            self.storage.as_ref(),
+            &self.events,
+            self.producer.as_ref(),
+            self.config.lease_timeout,
+        )
+        .await
+    }
+
+    pub async fn shutdown(&mut self) -> Result<(), TaskError> {
+        let _ = self.shutdown_tx.send(());
+
+        if let Some(handle) = self.handle.take() {
+            handle
+                .await
+                .map_err(|e| TaskError::ShutdownError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+async fn reap_expired(
+    broker: &dyn Broker,
+    storage: &dyn Storage,
+    events: &EventBus,
+    producer: Option<&Producer>,
+    lease_timeout: Duration,
+) -> Result<usize, TaskError> {
+    let lease_timeout = chrono::Duration::from_std(lease_timeout)
+        .map_err(|e| TaskError::InvalidArgument(e.to_string()))?;
+    let now = Utc::now();
+    let mut reaped = 0;
+
+    for task in storage.list_tasks().await? {
+        if !task.is_stale(now - lease_timeout) {
+            continue;
+        }
+        let last_seen = task.heartbeat_at().unwrap_or(task.created_at());
+
+        // Only one reaper gets to move the task out of `Running`, and only if
+        // its worker has stayed silent.
+        let Some(mut task) = storage.reclaim_task(task.id(), now - lease_timeout).await? else {
+            continue;
+        };
+
+        if task.retries() < task.max_retries() {
+            warn!("Requeueing {} abandoned since {}", task, last_seen);
+            task.increment_retries();
+            storage.update_task(&task).await?;
+            // Requeues only if the dead worker's reservation is still held;
+            // once it expired the broker has requeued the task itself.
+            broker.update_task(&task).await?;
+            broker.nack(task.id(), true).await?;
+            let event = TaskEvent::new(&task, TaskEventKind::Retried)
+                .with_reason(format!("Worker lost: no heartbeat since {}", last_seen));
+            events.emit(event).await;
+        } else {
+            warn!("Failing {} abandoned since {}", task, last_seen);
+            let reason = format!("Worker lost: no heartbeat since {}", last_seen);
+            task.set_status(TaskStatus::Failed(reason.clone()));
+            storage.update_task(&task).await?;
+            broker.ack(task.id()).await?;
+            broker.remove(task.id()).await?;
+            storage
+                .store_dead_letter(&DeadLetter::new(task.clone(), reason.clone()))
+                .await?;
+            events
+                .emit(TaskEvent::new(&task, TaskEventKind::Failed).with_reason(reason))
+                .await;
+            if let Some(producer) = producer {
+                producer.advance(&task).await?;
+            }
+        }
+        reaped += 1;
+    }
+
+    Ok(reaped)
+}
@@ -1,11 +1,14 @@
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock, TryLockError},
+    time::Duration,
 };
 
-use crate::core::TaskError;
+use crate::core::{RetryPolicy, TaskError};
 use async_trait::async_trait;
 
+use super::context::TaskContext;
+
 #[async_trait]
 pub trait TaskHandler: Send + Sync {
     async fn handle(
@@ -13,10 +16,73 @@ pub trait TaskHandler: Send + Sync {
         args: Vec<serde_json::Value>,
         kwargs: HashMap<String, serde_json::Value>,
     ) -> Result<Vec<u8>, TaskError>;
+
+    /// Called by the executor. Handlers that need the running task, e.g. to
+    /// stop when it is cancelled, override this instead of `handle`.
+    async fn handle_with_context(
+        &self,
+        ctx: &TaskContext,
+        args: Vec<serde_json::Value>,
+        kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        let _ = ctx;
+        self.handle(args, kwargs).await
+    }
+}
+
+/// Defaults applied to every task run by a handler, unless the task was
+/// enqueued with its own.
+#[derive(Debug, Clone, Default)]
+pub struct HandlerOptions {
+    pub(crate) soft_time_limit: Option<Duration>,
+    pub(crate) hard_time_limit: Option<Duration>,
+    pub(crate) retry_policy: RetryPolicy,
+}
+
+impl HandlerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_soft_time_limit(mut self, limit: Duration) -> Self {
+        self.soft_time_limit = Some(limit);
+        self
+    }
+
+    pub fn with_hard_time_limit(mut self, limit: Duration) -> Self {
+        self.hard_time_limit = Some(limit);
+        self
+    }
+
+    /// Used unless a task was enqueued with its own. Defaults to retrying
+    /// immediately.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    pub fn soft_time_limit(&self) -> Option<Duration> {
+        self.soft_time_limit
+    }
+
+    pub fn hard_time_limit(&self) -> Option<Duration> {
+        self.hard_time_limit
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+}
+
+type HandlerWithOptions = (Arc<dyn TaskHandler>, HandlerOptions);
+
+struct Registration {
+    handler: Arc<dyn TaskHandler>,
+    options: HandlerOptions,
 }
 
 pub struct TaskRegistry {
-    handlers: RwLock<HashMap<String, Arc<dyn TaskHandler>>>,
+    handlers: RwLock<HashMap<String, Registration>>,
 }
 
 impl TaskRegistry {
@@ -27,12 +93,28 @@ impl TaskRegistry {
     }
 
     pub fn register<H>(&self, name: &str, handler: H) -> Result<(), TaskError>
+    where
+        H: TaskHandler + 'static,
+    {
+        self.register_with_options(name, handler, HandlerOptions::default())
+    }
+
+    pub fn register_with_options<H>(
+        &self,
+        name: &str,
+        handler: H,
+        options: HandlerOptions,
+    ) -> Result<(), TaskError>
     where
         H: TaskHandler + 'static,
     {
         match self.handlers.try_write() {
             Ok(mut handlers) => {
-                handlers.insert(name.to_string(), Arc::new(handler));
+                let registration = Registration {
+                    handler: Arc::new(handler),
+                    options,
+                };
+                handlers.insert(name.to_string(), registration);
                 Ok(())
             }
             Err(TryLockError::WouldBlock) => Err(TaskError::RegistryLocked(
@@ -45,8 +127,17 @@ impl TaskRegistry {
     }
 
     pub fn get(&self, name: &str) -> Result<Option<Arc<dyn TaskHandler>>, TaskError> {
+        Ok(self.get_with_options(name)?.map(|(handler, _)| handler))
+    }
+
+    pub fn get_with_options(&self, name: &str) -> Result<Option<HandlerWithOptions>, TaskError> {
         match self.handlers.try_read() {
-            Ok(handlers) => Ok(handlers.get(name).map(Arc::clone)),
+            Ok(handlers) => Ok(handlers.get(name).map(|registration| {
+                (
+                    Arc::clone(&registration.handler),
+                    registration.options.clone(),
+                )
+            })),
             Err(TryLockError::WouldBlock) => Err(TaskError::RegistryLocked(
                 "Failed to acquire read lock".into(),
             )),
@@ -4,6 +4,8 @@ mod tests {
     use bg_coor::broker::redis::RedisBroker;
     use bg_coor::broker::traits::Broker;
     use bg_coor::core::Task;
+    use chrono::Utc;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_memory_broker() {
@@ -27,6 +29,176 @@ mod tests {
     }
 
     #[tokio::test]
+    async fn test_memory_broker_visibility_timeout() {
+        let broker = MemoryBroker::new().with_visibility_timeout(Duration::from_millis(50));
+        let task = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&task).await.unwrap();
+
+        // Reserved tasks are hidden until the visibility timeout expires
+        let reserved = broker.pop().await.unwrap().unwrap();
+        assert_eq!(reserved.id(), task.id());
+        assert!(broker.pop().await.unwrap().is_none());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let redelivered = broker.pop().await.unwrap().unwrap();
+        assert_eq!(redelivered.id(), task.id());
+
+        // Acked tasks are never redelivered
+        broker.ack(task.id()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(broker.pop().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_broker_extend() {
+        let broker = MemoryBroker::new().with_visibility_timeout(Duration::from_millis(100));
+        let task = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&task).await.unwrap();
+        assert!(!broker.extend(task.id()).await.unwrap());
+
+        // Extended reservations outlive the original timeout
+        broker.pop().await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(broker.extend(task.id()).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(broker.pop().await.unwrap().is_none());
+
+        broker.ack(task.id()).await.unwrap();
+        assert!(!broker.extend(task.id()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_broker_nack() {
+        let broker = MemoryBroker::new();
+        let task = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&task).await.unwrap();
+
+        let reserved = broker.pop().await.unwrap().unwrap();
+        broker.nack(reserved.id(), true).await.unwrap();
+        let requeued = broker.pop().await.unwrap().unwrap();
+        assert_eq!(requeued.id(), task.id());
+
+        broker.nack(requeued.id(), false).await.unwrap();
+        assert!(broker.pop().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_broker_priority() {
+        let broker = MemoryBroker::new();
+        let mut tasks = Vec::new();
+        for priority in [1, 5, 1, 9] {
+            let mut task = Task::new("test_task".to_string(), vec![], 3);
+            task.set_priority(priority);
+            broker.push(&task).await.unwrap();
+            tasks.push(task);
+        }
+
+        // Highest priority first, FIFO within a priority level
+        for expected in [3, 1, 0, 2] {
+            let popped = broker.pop().await.unwrap().unwrap();
+            assert_eq!(popped.id(), tasks[expected].id());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_broker_priority_aging() {
+        let broker = MemoryBroker::new().with_priority_aging(Duration::from_millis(10));
+        let low = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&low).await.unwrap();
+
+        // After waiting more than two aging steps the low priority task
+        // outranks a freshly enqueued task of priority 2
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut high = Task::new("test_task".to_string(), vec![], 3);
+        high.set_priority(2);
+        broker.push(&high).await.unwrap();
+
+        assert_eq!(broker.pop().await.unwrap().unwrap().id(), low.id());
+        assert_eq!(broker.pop().await.unwrap().unwrap().id(), high.id());
+    }
+
+    #[tokio::test]
+    async fn test_memory_broker_eta() {
+        let broker = MemoryBroker::new();
+        let mut delayed = Task::new("test_task".to_string(), vec![], 3);
+        delayed.set_priority(9);
+        delayed.set_eta(Some(Utc::now() + chrono::Duration::milliseconds(100)));
+        broker.push(&delayed).await.unwrap();
+        let immediate = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&immediate).await.unwrap();
+
+        // Delayed tasks are never popped before their ETA, whatever their priority
+        assert_eq!(broker.pop().await.unwrap().unwrap().id(), immediate.id());
+        assert!(broker.pop().await.unwrap().is_none());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(broker.pop().await.unwrap().unwrap().id(), delayed.id());
+    }
+
+    #[tokio::test]
+    async fn test_memory_broker_remove() {
+        let broker = MemoryBroker::new();
+        let queued = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&queued).await.unwrap();
+        let mut delayed = Task::new("test_task".to_string(), vec![], 3);
+        delayed.set_eta(Some(Utc::now() + chrono::Duration::seconds(60)));
+        broker.push(&delayed).await.unwrap();
+        assert_eq!(broker.queue_length().await.unwrap(), 2);
+
+        assert!(broker.remove(queued.id()).await.unwrap());
+        assert!(broker.remove(delayed.id()).await.unwrap());
+        assert!(!broker.remove(queued.id()).await.unwrap());
+        assert_eq!(broker.queue_length().await.unwrap(), 0);
+        assert!(broker.pop().await.unwrap().is_none());
+
+        // Reserved tasks are not removed
+        let reserved = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&reserved).await.unwrap();
+        broker.pop().await.unwrap().unwrap();
+        assert!(!broker.remove(reserved.id()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_broker_unique() {
+        let broker = MemoryBroker::new();
+        let first = uuid::Uuid::new_v4();
+        let second = uuid::Uuid::new_v4();
+        let window = Duration::from_millis(200);
+        assert_eq!(
+            broker.lock_unique("key", first, window).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            broker.lock_unique("key", second, window).await.unwrap(),
+            Some(first)
+        );
+        assert_eq!(
+            broker.lock_unique("other", second, window).await.unwrap(),
+            None
+        );
+
+        // Only the holder frees a key
+        broker.unlock_unique("other", first).await.unwrap();
+        assert_eq!(
+            broker.lock_unique("other", first, window).await.unwrap(),
+            Some(second)
+        );
+        broker.unlock_unique("other", second).await.unwrap();
+        assert_eq!(
+            broker.lock_unique("other", first, window).await.unwrap(),
+            None
+        );
+
+        // The key is free again once the window has passed
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(
+            broker.lock_unique("key", second, window).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::assertions_on_constants)]
     async fn test_redis_broker() {
         let queue_name = format!("test_queue_{}", uuid::Uuid::new_v4());
         let broker = RedisBroker::new("redis://127.0.0.1:6379", &queue_name).unwrap();
@@ -48,4 +220,137 @@ mod tests {
         let empty = broker.pop().await.unwrap();
         assert!(empty.is_none());
     }
+
+    #[tokio::test]
+    async fn test_redis_broker_headers() {
+        let queue_name = format!("test_queue_{}", uuid::Uuid::new_v4());
+        let broker = RedisBroker::new("redis://127.0.0.1:6379", &queue_name).unwrap();
+        let mut task = Task::new("test_task".to_string(), vec![], 3);
+        task.set_header("tenant", "acme");
+        broker.push(&task).await.unwrap();
+
+        let popped = broker.pop().await.unwrap().unwrap();
+        assert_eq!(popped.headers()["tenant"], "acme");
+    }
+
+    #[tokio::test]
+    async fn test_redis_broker_ack_nack() {
+        let queue_name = format!("test_queue_{}", uuid::Uuid::new_v4());
+        let broker = RedisBroker::new("redis://127.0.0.1:6379", &queue_name)
+            .unwrap()
+            .with_visibility_timeout(Duration::from_millis(200));
+        let task = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&task).await.unwrap();
+
+        // Unacked reservations are redelivered after the visibility timeout
+        let reserved = broker.pop().await.unwrap().unwrap();
+        assert!(broker.pop().await.unwrap().is_none());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let redelivered = broker.pop().await.unwrap().unwrap();
+        assert_eq!(redelivered.id(), reserved.id());
+
+        // Nack with requeue hands the task out again immediately
+        broker.nack(task.id(), true).await.unwrap();
+        let requeued = broker.pop().await.unwrap().unwrap();
+        assert_eq!(requeued.id(), task.id());
+
+        broker.ack(task.id()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(broker.pop().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redis_broker_extend() {
+        let queue_name = format!("test_queue_{}", uuid::Uuid::new_v4());
+        let broker = RedisBroker::new("redis://127.0.0.1:6379", &queue_name)
+            .unwrap()
+            .with_visibility_timeout(Duration::from_millis(200));
+        let task = Task::new("test_task".to_string(), vec![], 3);
+        broker.push(&task).await.unwrap();
+        assert!(!broker.extend(task.id()).await.unwrap());
+
+        // Extended reservations outlive the original timeout
+        broker.pop().await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert!(broker.extend(task.id()).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert!(broker.pop().await.unwrap().is_none());
+
+        broker.ack(task.id()).await.unwrap();
+        assert!(!broker.extend(task.id()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_redis_broker_priority() {
+        let queue_name = format!("test_queue_{}", uuid::Uuid::new_v4());
+        let broker = RedisBroker::new("redis://127.0.0.1:6379", &queue_name).unwrap();
+        let mut tasks = Vec::new();
+        for priority in [1, 5, 1, 9] {
+            let mut task = Task::new("test_task".to_string(), vec![], 3);
+            task.set_priority(priority);
+            broker.push(&task).await.unwrap();
+            tasks.push(task);
+            // Keep enqueue timestamps distinct so FIFO order is observable
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        for expected in [3, 1, 0, 2] {
+            let popped = broker.pop().await.unwrap().unwrap();
+            assert_eq!(popped.id(), tasks[expected].id());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redis_broker_eta() {
+        let queue_name = format!("test_queue_{}", uuid::Uuid::new_v4());
+        let broker = RedisBroker::new("redis://127.0.0.1:6379", &queue_name).unwrap();
+        let mut delayed = Task::new("test_task".to_string(), vec![], 3);
+        delayed.set_eta(Some(Utc::now() + chrono::Duration::milliseconds(200)));
+        broker.push(&delayed).await.unwrap();
+
+        assert!(broker.pop().await.unwrap().is_none());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(broker.pop().await.unwrap().unwrap().id(), delayed.id());
+    }
+
+    #[tokio::test]
+    async fn test_redis_broker_unique() {
+        let queue_name = format!("test_queue_{}", uuid::Uuid::new_v4());
+        let broker = RedisBroker::new("redis://127.0.0.1:6379", &queue_name).unwrap();
+        let first = uuid::Uuid::new_v4();
+        let second = uuid::Uuid::new_v4();
+        let window = Duration::from_millis(200);
+        assert_eq!(
+            broker.lock_unique("key", first, window).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            broker.lock_unique("key", second, window).await.unwrap(),
+            Some(first)
+        );
+        assert_eq!(
+            broker.lock_unique("other", second, window).await.unwrap(),
+            None
+        );
+
+        // Only the holder frees a key
+        broker.unlock_unique("other", first).await.unwrap();
+        assert_eq!(
+            broker.lock_unique("other", first, window).await.unwrap(),
+            Some(second)
+        );
+        broker.unlock_unique("other", second).await.unwrap();
+        assert_eq!(
+            broker.lock_unique("other", first, window).await.unwrap(),
+            None
+        );
+
+        // The key is free again once the window has passed
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(
+            broker.lock_unique("key", second, window).await.unwrap(),
+            None
+        );
+    }
 }
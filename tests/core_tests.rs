@@ -1,5 +1,9 @@
-use bg_coor::core::{Task, TaskError, TaskStatus};
+use bg_coor::core::{
+    Jitter, RetryPolicy, Task, TaskError, TaskProgress, TaskSignature, TaskStatus, Unique,
+    MAX_RETRY_DELAY,
+};
 use chrono::Utc;
+use std::time::Duration;
 
 #[test]
 fn test_task_creation() {
@@ -44,6 +48,16 @@ fn test_task_serialization() {
     assert_eq!(task.status(), deserialized.status());
 }
 
+#[test]
+fn test_task_headers_serialization() {
+    let mut task = Task::new("test".to_string(), vec![1, 2, 3], 3);
+    task.set_header("correlation_id", "abc");
+    let serialized = serde_json::to_string(&task).unwrap();
+    let deserialized: Task = serde_json::from_str(&serialized).unwrap();
+
+    assert_eq!(deserialized.headers()["correlation_id"], "abc");
+}
+
 #[test]
 fn test_task_display() {
     let task = Task::new("test_task".to_string(), vec![], 3);
@@ -60,3 +74,92 @@ fn test_task_error_conversions() {
     let err = TaskError::MaxRetriesExceeded;
     assert_eq!(err.to_string(), "Maximum retries exceeded");
 }
+
+#[test]
+fn test_retry_policy_backoff() {
+    let secs = Duration::from_secs;
+
+    let fixed = RetryPolicy::fixed(secs(5));
+    assert_eq!(fixed.delay(1, None), secs(5));
+    assert_eq!(fixed.delay(4, Some(secs(5))), secs(5));
+
+    let linear = RetryPolicy::linear(secs(1), secs(2));
+    assert_eq!(linear.delay(1, None), secs(1));
+    assert_eq!(linear.delay(3, None), secs(5));
+
+    let exponential = RetryPolicy::exponential(secs(1), secs(10));
+    assert_eq!(exponential.delay(1, None), secs(1));
+    assert_eq!(exponential.delay(3, None), secs(4));
+    assert_eq!(exponential.delay(5, None), secs(10));
+    assert_eq!(exponential.delay(100, None), secs(10));
+}
+
+#[test]
+fn test_retry_policy_jitter() {
+    let secs = Duration::from_secs;
+
+    let exponential = RetryPolicy::exponential(secs(1), secs(10));
+    let full = exponential.clone().with_jitter(Jitter::Full);
+    for attempt in 1..10 {
+        assert!(full.delay(attempt, None) <= exponential.delay(attempt, None));
+    }
+
+    let decorrelated = exponential.with_jitter(Jitter::Decorrelated);
+    for _ in 0..100 {
+        let delay = decorrelated.delay(2, Some(secs(2)));
+        assert!(delay >= secs(1) && delay <= secs(6));
+        assert!(decorrelated.delay(5, Some(secs(8))) <= secs(10));
+    }
+}
+
+#[test]
+fn test_task_progress_percentage() {
+    assert_eq!(TaskProgress::new(1, 4).percentage(), Some(25.0));
+    assert_eq!(TaskProgress::new(0, 0).percentage(), None);
+    assert_eq!(
+        TaskProgress::from_percentage(150.0).percentage(),
+        Some(100.0)
+    );
+
+    let progress = TaskProgress::new(3, 10).with_meta(serde_json::json!({ "file": "a.bin" }));
+    let serialized = serde_json::to_string(&progress).unwrap();
+    let deserialized: TaskProgress = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(deserialized, progress);
+}
+
+#[test]
+fn test_unique_key() {
+    let signature = |kwargs: &[(&str, i64)], arg: i64| {
+        TaskSignature::new(
+            "ingest".to_string(),
+            vec![serde_json::json!(arg)],
+            kwargs
+                .iter()
+                .map(|(key, value)| (key.to_string(), serde_json::json!(value)))
+                .collect(),
+        )
+    };
+    let unique = Unique::by_signature();
+    let key = unique.key_for(&signature(&[("a", 1), ("b", 2)], 1));
+
+    assert!(key.starts_with("ingest:"));
+    assert_eq!(key, unique.key_for(&signature(&[("b", 2), ("a", 1)], 1)));
+    assert_ne!(key, unique.key_for(&signature(&[("a", 1), ("b", 2)], 2)));
+    assert_ne!(key, unique.key_for(&signature(&[("a", 1)], 1)));
+    assert_eq!(
+        Unique::by_key("webhook-42").key_for(&signature(&[], 1)),
+        "webhook-42"
+    );
+}
+
+#[test]
+fn test_schedule_retry_caps_delay() {
+    let mut task = Task::new("test".to_string(), vec![], 3);
+    task.schedule_retry(Duration::MAX);
+
+    assert_eq!(task.retry_delay(), Some(MAX_RETRY_DELAY));
+    let next_retry_at = task.next_retry_at().unwrap();
+    assert!(next_retry_at <= Utc::now() + chrono::Duration::days(365));
+    assert!(next_retry_at > Utc::now() + chrono::Duration::days(364));
+    assert_eq!(task.eta(), Some(next_retry_at));
+}
@@ -0,0 +1,85 @@
+use bg_coor::core::{EnqueueOptions, TaskError, TaskSignature};
+use bg_coor::events::redis::{RedisEventPublisher, RedisEventSubscriber};
+use bg_coor::events::TaskEventKind;
+use bg_coor::task_manager::TaskManager;
+use bg_coor::worker::registry::TaskHandler;
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::time::Duration;
+
+struct FlakyHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for FlakyHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        Err(TaskError::ExecutionError("boom".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_lifecycle_events() {
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("flaky", FlakyHandler).unwrap();
+    let mut events = Box::pin(manager.subscribe_events());
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("flaky".to_string(), vec![], HashMap::new());
+    let id = manager
+        .enqueue_task_with_options(signature, EnqueueOptions::new().with_max_retries(1))
+        .await
+        .unwrap();
+
+    let mut kinds = Vec::new();
+    while kinds.last() != Some(&TaskEventKind::Failed) {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.task_id, id);
+        if event.kind != TaskEventKind::Enqueued {
+            assert!(event.worker_id.is_some());
+        }
+        kinds.push(event.kind);
+    }
+
+    assert_eq!(
+        kinds,
+        vec![
+            TaskEventKind::Enqueued,
+            TaskEventKind::Started,
+            TaskEventKind::Retried,
+            TaskEventKind::Started,
+            TaskEventKind::Failed,
+        ]
+    );
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_redis_events() {
+    let redis_url = "redis://localhost:6379";
+    let channel = "test_events";
+    let subscriber = RedisEventSubscriber::new(redis_url, channel).unwrap();
+    let mut remote = Box::pin(subscriber.subscribe().await.unwrap());
+
+    let manager = TaskManager::builder(1)
+        .with_event_publisher(RedisEventPublisher::new(redis_url, channel).unwrap())
+        .build();
+    let signature = TaskSignature::new("remote".to_string(), vec![], HashMap::new());
+    let id = manager
+        .enqueue_task_with_options(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+
+    let event = tokio::time::timeout(Duration::from_secs(5), remote.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.task_id, id);
+    assert_eq!(event.kind, TaskEventKind::Enqueued);
+}
@@ -0,0 +1,79 @@
+use bg_coor::lock::leader::LeaderElector;
+use bg_coor::lock::memory::MemoryLock;
+use bg_coor::lock::redis::RedisLock;
+use bg_coor::lock::traits::Lock;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_memory_lock() {
+    let lock = MemoryLock::new();
+    let ttl = Duration::from_millis(50);
+
+    assert!(lock.try_acquire("key", "a", ttl).await.unwrap());
+    assert!(!lock.try_acquire("key", "b", ttl).await.unwrap());
+    assert!(lock.renew("key", "a", ttl).await.unwrap());
+    assert!(!lock.renew("key", "b", ttl).await.unwrap());
+
+    // Expired leases can be taken over, and the old holder can no longer renew
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(lock.try_acquire("key", "b", ttl).await.unwrap());
+    assert!(!lock.renew("key", "a", ttl).await.unwrap());
+
+    assert!(!lock.release("key", "a").await.unwrap());
+    assert!(lock.release("key", "b").await.unwrap());
+    assert!(lock.try_acquire("key", "a", ttl).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_leader_election_failover() {
+    let lock: Arc<dyn Lock> = Arc::new(MemoryLock::new());
+    let ttl = Duration::from_millis(50);
+    let mut first = LeaderElector::new(lock.clone(), "scheduler").with_ttl(ttl);
+    let mut second = LeaderElector::new(lock.clone(), "scheduler").with_ttl(ttl);
+
+    assert!(first.campaign().await.unwrap());
+    assert!(!second.campaign().await.unwrap());
+    assert!(first.is_leader());
+    assert!(!second.is_leader());
+
+    // The leader stops renewing; the other candidate takes over once the
+    // lease expires
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(second.campaign().await.unwrap());
+    assert!(!first.campaign().await.unwrap());
+    assert!(!first.is_leader());
+
+    // Shutting down the leader hands the lease over immediately
+    second.shutdown().await.unwrap();
+    assert!(first.campaign().await.unwrap());
+    first.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_redis_lock() {
+    let prefix = format!("test_lock_{}", uuid::Uuid::new_v4());
+    let lock = RedisLock::new("redis://127.0.0.1:6379", &prefix).unwrap();
+    let ttl = Duration::from_millis(200);
+
+    assert!(lock.try_acquire("key", "a", ttl).await.unwrap());
+    assert!(!lock.try_acquire("key", "b", ttl).await.unwrap());
+    assert!(lock.renew("key", "a", ttl).await.unwrap());
+    assert!(!lock.renew("key", "b", ttl).await.unwrap());
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(lock.try_acquire("key", "b", ttl).await.unwrap());
+    assert!(!lock.release("key", "a").await.unwrap());
+    assert!(lock.release("key", "b").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_redis_lock_zero_ttl() {
+    let prefix = format!("test_lock_{}", uuid::Uuid::new_v4());
+    let lock = RedisLock::new("redis://127.0.0.1:6379", &prefix).unwrap();
+
+    assert!(lock.try_acquire("key", "a", Duration::ZERO).await.unwrap());
+    assert!(lock.renew("key", "a", Duration::ZERO).await.unwrap());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(lock.try_acquire("key", "b", Duration::ZERO).await.unwrap());
+}
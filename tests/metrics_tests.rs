@@ -0,0 +1,88 @@
+#![cfg(feature = "metrics")]
+
+use bg_coor::core::{EnqueueOptions, TaskError, TaskSignature};
+use bg_coor::metrics::Metrics;
+use bg_coor::task_manager::TaskManager;
+use bg_coor::worker::registry::TaskHandler;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Succeeds unless its first argument is "fail".
+struct MaybeHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for MaybeHandler {
+    async fn handle(
+        &self,
+        args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        if args.first() == Some(&serde_json::json!("fail")) {
+            return Err(TaskError::Fail("asked to".to_string()));
+        }
+        Ok(vec![])
+    }
+}
+
+async fn scrape(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let metrics = Metrics::new().unwrap();
+    let mut manager = TaskManager::builder(1)
+        .with_metrics(metrics.clone())
+        .build();
+    manager.register_handler("maybe", MaybeHandler).unwrap();
+    manager.start().await.unwrap();
+
+    for outcome in ["ok", "ok", "fail"] {
+        let signature = TaskSignature::new(
+            "maybe".to_string(),
+            vec![serde_json::json!(outcome)],
+            HashMap::new(),
+        );
+        let handle = manager
+            .enqueue_task_with_handle(signature, EnqueueOptions::new())
+            .await
+            .unwrap();
+        handle.wait_timeout(Duration::from_secs(5)).await.unwrap();
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move { metrics.serve(listener).await });
+
+    // The last task's event and worker release may trail its completion
+    let mut response = scrape(addr, "/metrics").await;
+    for _ in 0..50 {
+        if response.contains("bg_coor_busy_workers 0")
+            && response.contains("bg_coor_tasks_failed_total{task=\"maybe\"} 1")
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        response = scrape(addr, "/metrics").await;
+    }
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("bg_coor_tasks_enqueued_total{task=\"maybe\"} 3"));
+    assert!(response.contains("bg_coor_tasks_succeeded_total{task=\"maybe\"} 2"));
+    assert!(response.contains("bg_coor_tasks_failed_total{task=\"maybe\"} 1"));
+    assert!(response.contains("bg_coor_task_execution_seconds_count{task=\"maybe\"} 3"));
+    assert!(response.contains("bg_coor_task_queue_wait_seconds_count{task=\"maybe\"} 3"));
+    assert!(response.contains("bg_coor_busy_workers 0"));
+    assert!(response.contains("bg_coor_queue_depth 0"));
+
+    assert!(scrape(addr, "/").await.starts_with("HTTP/1.1 404"));
+
+    server.abort();
+    manager.shutdown().await.unwrap();
+}
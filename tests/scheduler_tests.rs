@@ -0,0 +1,150 @@
+use bg_coor::broker::memory::MemoryBroker;
+use bg_coor::broker::traits::Broker;
+use bg_coor::core::TaskSignature;
+use bg_coor::lock::leader::LeaderElector;
+use bg_coor::lock::memory::MemoryLock;
+use bg_coor::lock::traits::Lock;
+use bg_coor::producer::Producer;
+use bg_coor::scheduler::{PeriodicTask, Schedule, Scheduler};
+use bg_coor::storage::{MemoryStorage, Storage};
+use chrono::{TimeZone, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn test_cron_schedule_timezone() {
+    // 02:00 every day in Tokyo is 17:00 UTC the previous day
+    let schedule = Schedule::cron_with_timezone("0 0 2 * * *", chrono_tz::Asia::Tokyo).unwrap();
+    let after = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+
+    let next = schedule.next_after(after).unwrap();
+    assert_eq!(next, Utc.with_ymd_and_hms(2024, 1, 1, 17, 0, 0).unwrap());
+
+    assert!(Schedule::cron("not a cron expression").is_err());
+}
+
+#[test]
+fn test_schedule_last_due_coalesces_missed_runs() {
+    let schedule = Schedule::interval(Duration::from_secs(300));
+    let last_run = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+
+    // Not due yet
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 4, 0).unwrap();
+    assert!(schedule.last_due(last_run, now).is_none());
+
+    // Three runs were missed; only the latest is due
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 17, 0).unwrap();
+    let due = schedule.last_due(last_run, now).unwrap();
+    assert_eq!(due, Utc.with_ymd_and_hms(2024, 1, 1, 0, 15, 0).unwrap());
+
+    let schedule = Schedule::cron("0 0 * * * *").unwrap();
+    let due = schedule.last_due(last_run, now + chrono::Duration::hours(3));
+    assert_eq!(
+        due,
+        Some(Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap())
+    );
+}
+
+#[test]
+fn test_cron_last_due_after_long_outage() {
+    // A year of per-second runs resolves without walking each one
+    let schedule = Schedule::cron("* * * * * *").unwrap();
+    let last_run = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let started = std::time::Instant::now();
+    assert_eq!(schedule.last_due(last_run, now), Some(now));
+    let now = now + chrono::Duration::milliseconds(1500);
+    assert_eq!(
+        schedule.last_due(last_run, now),
+        Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 1).unwrap())
+    );
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    // A single missed run
+    let schedule = Schedule::cron("0 0 * * * *").unwrap();
+    let now = Utc.with_ymd_and_hms(2023, 1, 1, 1, 30, 0).unwrap();
+    assert_eq!(
+        schedule.last_due(last_run, now),
+        Some(Utc.with_ymd_and_hms(2023, 1, 1, 1, 0, 0).unwrap())
+    );
+}
+
+#[tokio::test]
+async fn test_scheduler_persists_last_run() {
+    let broker = Arc::new(MemoryBroker::new());
+    let storage = Arc::new(MemoryStorage::new());
+    let producer = Producer::new(broker.clone(), storage.clone());
+    let signature = TaskSignature::new("cleanup".to_string(), vec![], HashMap::new());
+    let periodic = PeriodicTask::new(
+        "nightly_cleanup",
+        Schedule::interval(Duration::from_secs(60)),
+        signature,
+    );
+
+    // A new schedule starts counting from now
+    let scheduler =
+        Scheduler::new(producer.clone(), storage.clone()).with_periodic_task(periodic.clone());
+    assert_eq!(scheduler.run_pending().await, 0);
+    assert!(storage
+        .load_schedule_last_run("nightly_cleanup")
+        .await
+        .unwrap()
+        .is_some());
+
+    // Pretend the last run was long ago: it fires exactly once
+    let last_run = Utc::now() - chrono::Duration::minutes(10);
+    storage
+        .store_schedule_last_run("nightly_cleanup", last_run)
+        .await
+        .unwrap();
+    assert_eq!(scheduler.run_pending().await, 1);
+    assert_eq!(scheduler.run_pending().await, 0);
+
+    let task = broker.pop().await.unwrap().unwrap();
+    assert_eq!(task.name(), "cleanup");
+    assert!(broker.pop().await.unwrap().is_none());
+
+    // A restarted scheduler sharing the storage does not fire again
+    let restarted = Scheduler::new(producer, storage.clone()).with_periodic_task(periodic);
+    assert_eq!(restarted.run_pending().await, 0);
+
+    // Nor does one that stopped before recording the run it enqueued
+    storage
+        .store_schedule_last_run("nightly_cleanup", last_run)
+        .await
+        .unwrap();
+    restarted.run_pending().await;
+    assert!(broker.pop().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_scheduler_only_leader_fires() {
+    let broker = Arc::new(MemoryBroker::new());
+    let storage = Arc::new(MemoryStorage::new());
+    let producer = Producer::new(broker.clone(), storage.clone());
+    let lock: Arc<dyn Lock> = Arc::new(MemoryLock::new());
+    let periodic = PeriodicTask::new(
+        "sync",
+        Schedule::interval(Duration::from_secs(300)),
+        TaskSignature::new("sync".to_string(), vec![], HashMap::new()),
+    );
+    storage
+        .store_schedule_last_run("sync", Utc::now() - chrono::Duration::minutes(10))
+        .await
+        .unwrap();
+
+    let leader = Scheduler::new(producer.clone(), storage.clone())
+        .with_periodic_task(periodic.clone())
+        .with_leader_election(LeaderElector::new(lock.clone(), "beat"));
+    let follower = Scheduler::new(producer, storage.clone())
+        .with_periodic_task(periodic)
+        .with_leader_election(LeaderElector::new(lock, "beat"));
+
+    assert!(leader.leader_elector().unwrap().campaign().await.unwrap());
+    assert!(!follower.leader_elector().unwrap().campaign().await.unwrap());
+
+    assert_eq!(follower.run_pending().await, 0);
+    assert_eq!(leader.run_pending().await, 1);
+    assert_eq!(follower.run_pending().await, 0);
+}
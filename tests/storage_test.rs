@@ -1,6 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use bg_coor::core::{Task, TaskStatus};
+    use bg_coor::core::{
+        DeadLetter, GroupRecord, NodeState, Saga, SagaRecord, SagaStatus, Task, TaskProgress,
+        TaskSignature, TaskStatus, Workflow, WorkflowRecord, WorkflowStatus,
+    };
     use bg_coor::storage::{MemoryStorage, RedisStorage, Storage};
 
     #[tokio::test]
@@ -29,6 +32,118 @@ mod tests {
         assert!(storage.load_task(task.id()).await.unwrap().is_none());
     }
 
+    /// Only the first claim of a silent running task succeeds.
+    async fn check_reclaim_task(storage: &dyn Storage) {
+        let mut task = Task::new("test".to_string(), vec![], 3);
+        task.set_status(TaskStatus::Running);
+        task.heartbeat();
+        storage.store_task(&task).await.unwrap();
+
+        let before = task.heartbeat_at().unwrap();
+        assert!(storage
+            .reclaim_task(task.id(), before)
+            .await
+            .unwrap()
+            .is_none());
+        let after = before + chrono::Duration::seconds(1);
+        let reclaimed = storage.reclaim_task(task.id(), after).await.unwrap();
+        assert_eq!(reclaimed.unwrap().status(), &TaskStatus::Pending);
+        assert!(storage
+            .reclaim_task(task.id(), after)
+            .await
+            .unwrap()
+            .is_none());
+        let loaded = storage.load_task(task.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.status(), &TaskStatus::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_reclaim_task() {
+        check_reclaim_task(&MemoryStorage::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_reclaim_task() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        check_reclaim_task(&storage).await;
+    }
+
+    /// Revocations are dropped once the task finishes or is deleted.
+    async fn check_revocation_cleared(storage: &dyn Storage) {
+        let mut task = Task::new("test".to_string(), vec![], 3);
+        storage.store_task(&task).await.unwrap();
+        storage.revoke_task(task.id()).await.unwrap();
+        assert!(storage.is_revoked(task.id()).await.unwrap());
+        task.set_status(TaskStatus::Cancelled);
+        storage.update_task(&task).await.unwrap();
+        assert!(!storage.is_revoked(task.id()).await.unwrap());
+
+        // A finished task is not marked at all
+        storage.revoke_task(task.id()).await.unwrap();
+        assert!(!storage.is_revoked(task.id()).await.unwrap());
+
+        let other = Task::new("test".to_string(), vec![], 3);
+        storage.store_task(&other).await.unwrap();
+        storage.revoke_task(other.id()).await.unwrap();
+        storage.delete_task(other.id()).await.unwrap();
+        assert!(!storage.is_revoked(other.id()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_revocation_cleared() {
+        check_revocation_cleared(&MemoryStorage::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_revocation_cleared() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        check_revocation_cleared(&storage).await;
+    }
+
+    /// Nodes of a cancelled workflow never move to `Enqueued`.
+    async fn check_cancelled_workflow(storage: &dyn Storage) {
+        let signature = TaskSignature::new("test".to_string(), vec![], Default::default());
+        let workflow = Workflow::new()
+            .with_node("a", signature.clone())
+            .with_node("b", signature);
+        let record = WorkflowRecord::new(workflow);
+        storage.store_workflow(&record).await.unwrap();
+        storage
+            .transition_workflow_node(record.id(), "a", &[NodeState::Pending], NodeState::Enqueued)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(storage.cancel_workflow(record.id()).await.unwrap());
+        assert!(!storage.cancel_workflow(record.id()).await.unwrap());
+        assert!(!storage.cancel_workflow(uuid::Uuid::new_v4()).await.unwrap());
+        assert!(storage
+            .transition_workflow_node(record.id(), "b", &[NodeState::Pending], NodeState::Enqueued)
+            .await
+            .unwrap()
+            .is_none());
+        let updated = storage
+            .transition_workflow_node(
+                record.id(),
+                "a",
+                &[NodeState::Enqueued],
+                NodeState::Completed,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(updated.is_cancelled());
+        assert_eq!(updated.status(), WorkflowStatus::Cancelled);
+        assert_eq!(updated.node("b").unwrap().state(), NodeState::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_cancelled_workflow() {
+        check_cancelled_workflow(&MemoryStorage::new()).await;
+    }
+
     #[tokio::test]
     async fn test_redis_storage() {
         let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
@@ -58,4 +173,191 @@ mod tests {
         storage.delete_task(task.id()).await.unwrap();
         assert!(storage.load_task(task.id()).await.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn test_redis_storage_heartbeat_and_progress() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        let mut task = Task::new("test".to_string(), vec![1, 2, 3], 3);
+        task.set_status(TaskStatus::Running);
+        storage.store_task(&task).await.unwrap();
+
+        // Heartbeats and progress leave the rest of the task alone
+        let at = chrono::Utc::now();
+        storage.touch_heartbeat(task.id(), at).await.unwrap();
+        storage
+            .store_progress(task.id(), &TaskProgress::new(1, 2))
+            .await
+            .unwrap();
+        let loaded = storage.load_task(task.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.status(), &TaskStatus::Running);
+        assert_eq!(loaded.heartbeat_at(), Some(at));
+        assert_eq!(loaded.progress().unwrap().current(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_headers() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        let mut task = Task::new("test".to_string(), vec![1, 2, 3], 3);
+        task.set_header("tenant", "acme");
+
+        storage.store_task(&task).await.unwrap();
+        let loaded = storage.load_task(task.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.headers()["tenant"], "acme");
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_dead_letters() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        let task = Task::new("test".to_string(), vec![1, 2, 3], 3);
+        storage.store_task(&task).await.unwrap();
+
+        let letter = DeadLetter::new(task.clone(), "boom");
+        storage.store_dead_letter(&letter).await.unwrap();
+
+        let loaded = storage.load_dead_letter(task.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.reason(), "boom");
+        assert_eq!(storage.list_dead_letters().await.unwrap().len(), 1);
+        // Dead letters are not mistaken for tasks
+        assert_eq!(storage.list_tasks().await.unwrap().len(), 1);
+
+        assert!(storage.delete_dead_letter(task.id()).await.unwrap());
+        assert!(!storage.delete_dead_letter(task.id()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_groups() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        let members = vec![uuid::Uuid::new_v4(), uuid::Uuid::new_v4()];
+        let group = GroupRecord::new(members.clone(), None);
+        storage.store_group(&group).await.unwrap();
+
+        let loaded = storage.load_group(group.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.members(), &members[..]);
+
+        // Only the call finishing the last pending member reports it
+        assert!(!storage
+            .finish_group_member(group.id(), members[0])
+            .await
+            .unwrap());
+        assert!(!storage
+            .finish_group_member(group.id(), members[0])
+            .await
+            .unwrap());
+        assert!(storage
+            .finish_group_member(group.id(), members[1])
+            .await
+            .unwrap());
+        assert!(!storage
+            .finish_group_member(group.id(), members[1])
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_workflows() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        let signature = TaskSignature::new("test".to_string(), vec![], Default::default());
+        let workflow = Workflow::new()
+            .with_node("a", signature.clone())
+            .with_node("b", signature)
+            .on_success("a", "b");
+        let record = WorkflowRecord::new(workflow);
+        storage.store_workflow(&record).await.unwrap();
+
+        let updated = storage
+            .transition_workflow_node(record.id(), "a", &[NodeState::Pending], NodeState::Enqueued)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.node("a").unwrap().state(), NodeState::Enqueued);
+        assert_eq!(updated.node("b").unwrap().state(), NodeState::Pending);
+
+        // Nodes only move from the expected states
+        assert!(storage
+            .transition_workflow_node(record.id(), "a", &[NodeState::Pending], NodeState::Skipped)
+            .await
+            .unwrap()
+            .is_none());
+        let loaded = storage.load_workflow(record.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.node("a").unwrap().state(), NodeState::Enqueued);
+        assert!(!loaded.is_cancelled());
+
+        check_cancelled_workflow(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_sagas() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        let signature = TaskSignature::new("test".to_string(), vec![], Default::default());
+        let saga = SagaRecord::new(Saga::new().step(signature.clone(), signature));
+        storage.store_saga(&saga).await.unwrap();
+
+        let loaded = storage.load_saga(saga.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.status(), SagaStatus::Running);
+        assert_eq!(
+            loaded.steps()[0].action().id(),
+            saga.steps()[0].action().id()
+        );
+        assert!(storage
+            .load_saga(uuid::Uuid::new_v4())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_schedule_last_run() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap();
+        assert!(storage
+            .load_schedule_last_run("nightly")
+            .await
+            .unwrap()
+            .is_none());
+
+        let last_run = chrono::Utc::now();
+        storage
+            .store_schedule_last_run("nightly", last_run)
+            .await
+            .unwrap();
+        let loaded = storage.load_schedule_last_run("nightly").await.unwrap();
+        assert_eq!(loaded, Some(last_run));
+
+        // Schedule state never shows up as a task
+        assert!(storage.list_tasks().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_redis_storage_wait_for_completion() {
+        let storage_name = format!("test_storage_{}", uuid::Uuid::new_v4());
+        let storage = std::sync::Arc::new(
+            RedisStorage::new("redis://127.0.0.1:6379", &storage_name).unwrap(),
+        );
+        let task = Task::new("test".to_string(), vec![], 3);
+        storage.store_task(&task).await.unwrap();
+
+        let waiter = {
+            let storage = storage.clone();
+            let id = task.id();
+            tokio::spawn(async move { storage.wait_for_completion(id).await })
+        };
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let mut finished = task.clone();
+        finished.set_status(TaskStatus::Completed);
+        storage.update_task(&finished).await.unwrap();
+
+        let completed = tokio::time::timeout(std::time::Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.status(), &TaskStatus::Completed);
+    }
 }
@@ -0,0 +1,904 @@
+use bg_coor::core::{
+    EnqueueOptions, ErrorKind, OnDuplicate, RetryPolicy, Task, TaskError, TaskSignature,
+    TaskStatus, Unique, MAX_RETRY_DELAY,
+};
+use bg_coor::producer::EnqueueMiddleware;
+use bg_coor::task_manager::TaskManager;
+use bg_coor::worker::context::TaskContext;
+use bg_coor::worker::middleware::{Middleware, Next};
+use bg_coor::worker::registry::{HandlerOptions, TaskHandler};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+struct EchoHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for EchoHandler {
+    async fn handle(
+        &self,
+        args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        Ok(serde_json::to_vec(&args)?)
+    }
+}
+
+/// Runs until cancelled, or forever if `cooperative` is false.
+struct SleepyHandler {
+    cooperative: bool,
+}
+
+#[async_trait::async_trait]
+impl TaskHandler for SleepyHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        std::future::pending().await
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &TaskContext,
+        args: Vec<serde_json::Value>,
+        kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        if !self.cooperative {
+            return self.handle(args, kwargs).await;
+        }
+        ctx.cancelled().await;
+        Err(TaskError::Cancelled(ctx.id()))
+    }
+}
+
+/// Finishes early once its soft time limit is reached.
+struct WrapUpHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for WrapUpHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        std::future::pending().await
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &TaskContext,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        ctx.soft_time_limit_reached().await;
+        Ok(b"partial".to_vec())
+    }
+}
+
+/// Fails the way its first argument says.
+struct OutcomeHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for OutcomeHandler {
+    async fn handle(
+        &self,
+        args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        Err(match args[0].as_str().unwrap() {
+            "ok" => return Ok(b"ok".to_vec()),
+            "retry" => TaskError::Retry {
+                after: Some(Duration::from_secs(600)),
+            },
+            "retry_forever" => TaskError::Retry {
+                after: Some(Duration::MAX),
+            },
+            "fail" => TaskError::Fail("bad input".to_string()),
+            "ignore" => TaskError::Ignore,
+            "invalid" => TaskError::InvalidArgument("bad input".to_string()),
+            _ => TaskError::ExecutionError("flaky".to_string()),
+        })
+    }
+}
+
+async fn run_outcome(manager: &TaskManager, outcome: &str, options: EnqueueOptions) -> Task {
+    let signature = TaskSignature::new(
+        "outcome".to_string(),
+        vec![serde_json::json!(outcome)],
+        HashMap::new(),
+    );
+    let id = manager
+        .enqueue_task_with_options(signature, options)
+        .await
+        .unwrap();
+    loop {
+        let task = manager.get_task(id).await.unwrap().unwrap();
+        if task.is_finished() || task.status() == &TaskStatus::Scheduled {
+            return task;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Reports what it knows about itself and enqueues an echo child.
+struct ContextHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for ContextHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        unreachable!()
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &TaskContext,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        ctx.progress().update(1, 2);
+        let child = TaskSignature::new(
+            "echo".to_string(),
+            vec![serde_json::json!(ctx.id())],
+            HashMap::new(),
+        );
+        let child_id = ctx.enqueue(child, EnqueueOptions::new()).await?;
+        ctx.progress().update(2, 2);
+
+        Ok(serde_json::to_vec(&serde_json::json!({
+            "id": ctx.id(),
+            "name": ctx.name(),
+            "retries": ctx.retries(),
+            "max_retries": ctx.max_retries(),
+            "has_deadline": ctx.deadline().is_some(),
+            "headers": ctx.headers(),
+            "child_id": child_id,
+        }))?)
+    }
+}
+
+/// Reports a burst of progress, then keeps running for a while.
+struct ProgressHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for ProgressHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        unreachable!()
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &TaskContext,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        for file in 1..=100 {
+            let meta = serde_json::json!({ "file": format!("{}.bin", file) });
+            ctx.progress().update_with_meta(file, 400, meta);
+            tokio::task::yield_now().await;
+        }
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        ctx.progress().update_percentage(100.0);
+        Ok(vec![])
+    }
+}
+
+#[tokio::test]
+async fn test_enqueue_with_countdown() {
+    let manager = TaskManager::builder(1).build();
+    let signature = TaskSignature::new("test_task".to_string(), vec![], HashMap::new());
+
+    let options = EnqueueOptions::new()
+        .with_max_retries(3)
+        .with_countdown(Duration::from_secs(600));
+    let id = manager
+        .enqueue_task_with_options(signature, options)
+        .await
+        .unwrap();
+
+    let task = manager.get_task(id).await.unwrap().unwrap();
+    assert_eq!(task.status(), &TaskStatus::Scheduled);
+    assert!(task.eta().unwrap() >= task.created_at() + chrono::Duration::seconds(600));
+}
+
+#[tokio::test]
+async fn test_enqueue_with_handle() {
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("echo", EchoHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new(
+        "echo".to_string(),
+        vec![serde_json::json!("hello")],
+        HashMap::new(),
+    );
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+
+    let task = handle.wait_timeout(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(task.id(), handle.id());
+    assert_eq!(task.status(), &TaskStatus::Completed);
+    assert_eq!(handle.result().await.unwrap(), br#"["hello"]"#.to_vec());
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_handle_wait_timeout() {
+    let manager = TaskManager::builder(1).build();
+    let signature = TaskSignature::new("echo".to_string(), vec![], HashMap::new());
+
+    // No workers are running, so the task never finishes
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+
+    let result = handle.wait_timeout(Duration::from_millis(50)).await;
+    assert!(matches!(result, Err(TaskError::WaitTimeout(id)) if id == handle.id()));
+}
+
+#[tokio::test]
+async fn test_cancel_pending_task() {
+    let manager = TaskManager::builder(1).build();
+    let signature = TaskSignature::new("echo".to_string(), vec![], HashMap::new());
+    let id = manager
+        .enqueue_task_with_options(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+
+    assert!(manager.cancel(id).await.unwrap());
+    let task = manager.get_task(id).await.unwrap().unwrap();
+    assert_eq!(task.status(), &TaskStatus::Cancelled);
+
+    // Finished tasks cannot be cancelled again
+    assert!(!manager.cancel(id).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_cancel_running_task() {
+    let mut manager = TaskManager::builder(1).build();
+    manager
+        .register_handler("sleepy", SleepyHandler { cooperative: true })
+        .unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("sleepy".to_string(), vec![], HashMap::new());
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new().with_max_retries(3))
+        .await
+        .unwrap();
+    while !manager
+        .get_task(handle.id())
+        .await
+        .unwrap()
+        .unwrap()
+        .is_running()
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert!(manager.cancel(handle.id()).await.unwrap());
+    let task = handle.wait_timeout(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(task.status(), &TaskStatus::Cancelled);
+    assert_eq!(task.retries(), 0);
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cancel_aborts_after_grace_period() {
+    let mut manager = TaskManager::builder(1)
+        .with_cancel_grace_period(Duration::from_millis(100))
+        .build();
+    manager
+        .register_handler("sleepy", SleepyHandler { cooperative: false })
+        .unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("sleepy".to_string(), vec![], HashMap::new());
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+    while !manager
+        .get_task(handle.id())
+        .await
+        .unwrap()
+        .unwrap()
+        .is_running()
+    {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    manager.cancel(handle.id()).await.unwrap();
+    let task = handle.wait_timeout(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(task.status(), &TaskStatus::Cancelled);
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_hard_time_limit() {
+    let mut manager = TaskManager::builder(1).build();
+    manager
+        .register_handler_with_options(
+            "sleepy",
+            SleepyHandler { cooperative: false },
+            HandlerOptions::new().with_hard_time_limit(Duration::from_secs(60)),
+        )
+        .unwrap();
+    manager.start().await.unwrap();
+
+    // The enqueue option overrides the handler default
+    let signature = TaskSignature::new("sleepy".to_string(), vec![], HashMap::new());
+    let options = EnqueueOptions::new()
+        .with_max_retries(1)
+        .with_hard_time_limit(Duration::from_millis(50));
+    let handle = manager
+        .enqueue_task_with_handle(signature, options)
+        .await
+        .unwrap();
+
+    let task = handle.wait_timeout(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(task.status(), &TaskStatus::TimedOut);
+    assert_eq!(task.retries(), 1);
+    assert!(matches!(handle.result().await, Err(TaskError::TimedOut(id)) if id == handle.id()));
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_soft_time_limit() {
+    let mut manager = TaskManager::builder(1).build();
+    manager
+        .register_handler_with_options(
+            "wrap_up",
+            WrapUpHandler,
+            HandlerOptions::new()
+                .with_soft_time_limit(Duration::from_millis(50))
+                .with_hard_time_limit(Duration::from_secs(5)),
+        )
+        .unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("wrap_up".to_string(), vec![], HashMap::new());
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+
+    assert_eq!(handle.result().await.unwrap(), b"partial".to_vec());
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_retry_is_scheduled() {
+    let mut manager = TaskManager::builder(1).build();
+    manager
+        .register_handler_with_options(
+            "sleepy",
+            SleepyHandler { cooperative: false },
+            HandlerOptions::new()
+                .with_hard_time_limit(Duration::from_millis(10))
+                .with_retry_policy(RetryPolicy::fixed(Duration::from_secs(600))),
+        )
+        .unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("sleepy".to_string(), vec![], HashMap::new());
+    let id = manager
+        .enqueue_task_with_options(signature, EnqueueOptions::new().with_max_retries(3))
+        .await
+        .unwrap();
+
+    let task = loop {
+        let task = manager.get_task(id).await.unwrap().unwrap();
+        if task.retries() > 0 {
+            break task;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(task.status(), &TaskStatus::Scheduled);
+    assert_eq!(task.retry_delay(), Some(Duration::from_secs(600)));
+    let next_retry_at = task.next_retry_at().unwrap();
+    assert!(next_retry_at > chrono::Utc::now() + chrono::Duration::seconds(590));
+    assert_eq!(task.eta(), Some(next_retry_at));
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_handler_outcomes() {
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("outcome", OutcomeHandler).unwrap();
+    manager.start().await.unwrap();
+    let options = EnqueueOptions::new().with_max_retries(3);
+
+    let task = run_outcome(
+        &manager,
+        "retry",
+        options.clone().with_header("tenant", "acme"),
+    )
+    .await;
+    assert_eq!(task.status(), &TaskStatus::Scheduled);
+    assert_eq!(task.retries(), 1);
+    assert_eq!(task.headers()["tenant"], "acme");
+    assert_eq!(task.retry_delay(), Some(Duration::from_secs(600)));
+
+    // Delays too long to represent are capped rather than crashing the worker
+    let task = run_outcome(&manager, "retry_forever", options.clone()).await;
+    assert_eq!(task.status(), &TaskStatus::Scheduled);
+    assert_eq!(task.retry_delay(), Some(MAX_RETRY_DELAY));
+
+    let task = run_outcome(&manager, "fail", options.clone()).await;
+    assert_eq!(
+        task.status(),
+        &TaskStatus::Failed("Task failed permanently: bad input".to_string())
+    );
+    assert_eq!(task.retries(), 0);
+
+    let task = run_outcome(&manager, "ignore", options.clone()).await;
+    assert_eq!(task.status(), &TaskStatus::Ignored);
+
+    // Errors caused by the task itself are not retried by default
+    let task = run_outcome(&manager, "invalid", options.clone()).await;
+    assert!(matches!(task.status(), TaskStatus::Failed(_)));
+    assert_eq!(task.retries(), 0);
+
+    // Unless the retry policy says so, and only those it lists
+    let policy = RetryPolicy::immediate().with_retryable([ErrorKind::InvalidArgument]);
+    let task = run_outcome(
+        &manager,
+        "invalid",
+        options.clone().with_retry_policy(policy.clone()),
+    )
+    .await;
+    assert_eq!(task.retries(), 3);
+    let task = run_outcome(&manager, "flaky", options.with_retry_policy(policy)).await;
+    assert_eq!(task.retries(), 0);
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dead_letter_queue() {
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("outcome", OutcomeHandler).unwrap();
+    manager.start().await.unwrap();
+    let options = EnqueueOptions::new().with_max_retries(1);
+
+    let flaky = run_outcome(&manager, "flaky", options.clone()).await;
+    let failed = run_outcome(&manager, "fail", options.clone()).await;
+    run_outcome(&manager, "ignore", options.clone()).await;
+
+    let letters = manager.list_dead_letters().await.unwrap();
+    assert_eq!(letters.len(), 2);
+    let letter = manager.get_dead_letter(flaky.id()).await.unwrap().unwrap();
+    assert_eq!(letter.task().retries(), 1);
+    assert_eq!(letter.reason(), "Task execution failed: flaky");
+
+    // Replaying with fixed arguments runs the same task again
+    let id = manager
+        .replay_dead_letter_with_args(flaky.id(), vec![serde_json::json!("ok")], HashMap::new())
+        .await
+        .unwrap();
+    assert_eq!(id, flaky.id());
+    let task = manager
+        .task_handle(id)
+        .wait_timeout(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(task.status(), &TaskStatus::Completed);
+    assert_eq!(task.retries(), 0);
+    assert!(manager.get_dead_letter(id).await.unwrap().is_none());
+
+    // Dead letters are copies; the failed task stays until it is purged
+    let stored = manager.get_task(failed.id()).await.unwrap().unwrap();
+    assert!(matches!(stored.status(), TaskStatus::Failed(_)));
+    assert_eq!(manager.purge_dead_letters().await.unwrap(), 1);
+    assert!(manager
+        .get_dead_letter(failed.id())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(manager.get_task(failed.id()).await.unwrap().is_none());
+    assert!(!manager.purge_dead_letter(failed.id()).await.unwrap());
+    assert!(matches!(
+        manager.replay_dead_letter(failed.id()).await,
+        Err(TaskError::NotFound(_))
+    ));
+
+    manager.shutdown().await.unwrap();
+}
+
+/// Rejects every task enqueued without arguments.
+struct ArgumentsRequired;
+
+#[async_trait::async_trait]
+impl EnqueueMiddleware for ArgumentsRequired {
+    async fn before_enqueue(&self, task: &mut Task) -> Result<(), TaskError> {
+        if task.signature()?.args.is_empty() {
+            return Err(TaskError::ValidationError("arguments required".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_replay_goes_through_enqueue_middleware() {
+    let mut manager = TaskManager::builder(1)
+        .with_enqueue_middleware(ArgumentsRequired)
+        .build();
+    manager.register_handler("outcome", OutcomeHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let failed = run_outcome(&manager, "fail", EnqueueOptions::new()).await;
+    let rejected = manager
+        .replay_dead_letter_with_args(failed.id(), vec![], HashMap::new())
+        .await;
+    assert!(matches!(rejected, Err(TaskError::ValidationError(_))));
+    assert!(manager
+        .get_dead_letter(failed.id())
+        .await
+        .unwrap()
+        .is_some());
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_task_context() {
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("context", ContextHandler).unwrap();
+    manager.register_handler("echo", EchoHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("context".to_string(), vec![], HashMap::new());
+    let options = EnqueueOptions::new()
+        .with_max_retries(2)
+        .with_hard_time_limit(Duration::from_secs(60))
+        .with_header("correlation_id", "abc");
+    let handle = manager
+        .enqueue_task_with_handle(signature, options)
+        .await
+        .unwrap();
+
+    let result: serde_json::Value =
+        serde_json::from_slice(&handle.result().await.unwrap()).unwrap();
+    assert_eq!(result["id"], serde_json::json!(handle.id()));
+    assert_eq!(result["name"], "context");
+    assert_eq!(result["retries"], 0);
+    assert_eq!(result["max_retries"], 2);
+    assert_eq!(result["has_deadline"], true);
+    assert_eq!(result["headers"]["correlation_id"], "abc");
+
+    let task = manager.get_task(handle.id()).await.unwrap().unwrap();
+    assert_eq!(task.progress().unwrap().current(), Some(2));
+
+    let child_id = serde_json::from_value(result["child_id"].clone()).unwrap();
+    let child = manager
+        .task_handle(child_id)
+        .wait_timeout(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(child.status(), &TaskStatus::Completed);
+    assert_eq!(child.headers()["correlation_id"], "abc");
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_progress_reporting() {
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("scan", ProgressHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("scan".to_string(), vec![], HashMap::new());
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+
+    // The burst is throttled, but the latest report is written while running
+    let progress = tokio::time::timeout(Duration::from_millis(2500), async {
+        loop {
+            let task = manager.get_task(handle.id()).await.unwrap().unwrap();
+            match task.progress() {
+                Some(progress) if progress.current() == Some(100) => {
+                    assert!(task.is_running());
+                    break progress.clone();
+                }
+                _ => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(progress.total(), Some(400));
+    assert_eq!(progress.percentage(), Some(25.0));
+    assert_eq!(progress.meta().unwrap()["file"], "100.bin");
+
+    let task = handle.wait_timeout(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(task.progress().unwrap().percentage(), Some(100.0));
+
+    manager.shutdown().await.unwrap();
+}
+
+fn echo_signature(arg: &str) -> TaskSignature {
+    TaskSignature::new(
+        "echo".to_string(),
+        vec![serde_json::json!(arg)],
+        HashMap::new(),
+    )
+}
+
+/// Records every call it wraps, with its outcome.
+struct RecordingMiddleware {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait::async_trait]
+impl Middleware for RecordingMiddleware {
+    async fn handle(&self, task: &mut Task, next: Next<'_>) -> Result<Vec<u8>, TaskError> {
+        let result = next.run(task).await;
+        let outcome = match &result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("{:?}", e.kind()),
+        };
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("{}:{}", task.name(), outcome));
+        result
+    }
+}
+
+/// Answers "cached" tasks itself and rewrites the arguments of the others.
+struct RewritingMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for RewritingMiddleware {
+    async fn handle(&self, task: &mut Task, next: Next<'_>) -> Result<Vec<u8>, TaskError> {
+        let mut signature: TaskSignature = serde_json::from_slice(task.payload())?;
+        if signature.args.first() == Some(&serde_json::json!("cached")) {
+            return Ok(b"from cache".to_vec());
+        }
+        signature.args.push(serde_json::json!("rewritten"));
+        task.set_payload(serde_json::to_vec(&signature)?);
+        next.run(task).await
+    }
+}
+
+/// Rejects every task before it reaches the handler.
+struct RejectingMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for RejectingMiddleware {
+    async fn before_execution(&self, _task: &Task) -> Result<(), TaskError> {
+        Err(TaskError::Fail("rejected".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_chain() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let mut manager = TaskManager::builder(1)
+        .with_middleware(RecordingMiddleware {
+            calls: calls.clone(),
+        })
+        .with_middleware(RewritingMiddleware)
+        .build();
+    manager.register_handler("echo", EchoHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let id = manager
+        .enqueue_task(echo_signature("hello"), 0)
+        .await
+        .unwrap();
+    let task = manager
+        .task_handle(id)
+        .wait_timeout(Duration::from_secs(5))
+        .await
+        .unwrap();
+    let args: Vec<String> = serde_json::from_slice(task.get_result().unwrap()).unwrap();
+    assert_eq!(args, vec!["hello", "rewritten"]);
+
+    let id = manager
+        .enqueue_task(echo_signature("cached"), 0)
+        .await
+        .unwrap();
+    let task = manager
+        .task_handle(id)
+        .wait_timeout(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(task.get_result(), Some(&b"from cache"[..]));
+
+    assert_eq!(*calls.lock().unwrap(), vec!["echo:ok", "echo:ok"]);
+    manager.shutdown().await.unwrap();
+
+    let mut manager = TaskManager::builder(1)
+        .with_middleware(RecordingMiddleware {
+            calls: calls.clone(),
+        })
+        .with_middleware(RejectingMiddleware)
+        .build();
+    manager.register_handler("echo", EchoHandler).unwrap();
+    manager.start().await.unwrap();
+    let id = manager
+        .enqueue_task(echo_signature("hello"), 0)
+        .await
+        .unwrap();
+    let task = manager
+        .task_handle(id)
+        .wait_timeout(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert!(matches!(task.status(), TaskStatus::Failed(_)));
+    assert_eq!(calls.lock().unwrap().last().unwrap(), "echo:Fail");
+    manager.shutdown().await.unwrap();
+}
+
+/// Stamps every task with a trace id before it runs.
+struct TracingMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for TracingMiddleware {
+    async fn handle(&self, task: &mut Task, next: Next<'_>) -> Result<Vec<u8>, TaskError> {
+        task.set_header("trace_id", "t-1");
+        next.run(task).await
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_headers_reach_context() {
+    let mut manager = TaskManager::builder(1)
+        .with_middleware(TracingMiddleware)
+        .build();
+    manager.register_handler("context", ContextHandler).unwrap();
+    manager.register_handler("echo", EchoHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new("context".to_string(), vec![], HashMap::new());
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+    let result: serde_json::Value =
+        serde_json::from_slice(&handle.result().await.unwrap()).unwrap();
+    assert_eq!(result["headers"]["trace_id"], "t-1");
+    assert_eq!(result["id"], serde_json::json!(handle.id()));
+
+    // Children inherit the headers the handler saw
+    let child_id = serde_json::from_value(result["child_id"].clone()).unwrap();
+    let child = manager
+        .task_handle(child_id)
+        .wait_timeout(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(child.headers()["trace_id"], "t-1");
+
+    manager.shutdown().await.unwrap();
+}
+
+/// Tags every task with a tenant and rejects echo tasks without arguments.
+struct TenantMiddleware;
+
+#[async_trait::async_trait]
+impl EnqueueMiddleware for TenantMiddleware {
+    async fn before_enqueue(&self, task: &mut Task) -> Result<(), TaskError> {
+        let signature = task.signature()?;
+        if signature.name == "echo" && signature.args.is_empty() {
+            return Err(TaskError::ValidationError(
+                "echo needs arguments".to_string(),
+            ));
+        }
+        task.set_header("tenant", "acme");
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_enqueue_middleware() {
+    let mut manager = TaskManager::builder(1)
+        .with_enqueue_middleware(TenantMiddleware)
+        .build();
+    manager.register_handler("context", ContextHandler).unwrap();
+    manager.register_handler("echo", EchoHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let rejected = manager
+        .enqueue_task(
+            TaskSignature::new("echo".to_string(), vec![], HashMap::new()),
+            0,
+        )
+        .await;
+    assert!(matches!(rejected, Err(TaskError::ValidationError(_))));
+    assert!(manager.list_tasks().await.unwrap().is_empty());
+
+    // Children enqueued by handlers go through the same chain
+    let signature = TaskSignature::new("context".to_string(), vec![], HashMap::new());
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .await
+        .unwrap();
+    let result: serde_json::Value =
+        serde_json::from_slice(&handle.result().await.unwrap()).unwrap();
+    let child_id = serde_json::from_value(result["child_id"].clone()).unwrap();
+    for id in [handle.id(), child_id] {
+        let task = manager.get_task(id).await.unwrap().unwrap();
+        assert_eq!(task.headers()["tenant"], "acme");
+    }
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unique_tasks() {
+    let manager = TaskManager::builder(1).build();
+    let enqueue = |signature: TaskSignature, unique: Unique| {
+        manager.enqueue_task_with_options(signature, EnqueueOptions::new().with_unique(unique))
+    };
+
+    // Duplicates by signature get the id of the first task
+    let first = enqueue(echo_signature("a"), Unique::by_signature())
+        .await
+        .unwrap();
+    let duplicate = enqueue(echo_signature("a"), Unique::by_signature())
+        .await
+        .unwrap();
+    assert_eq!(duplicate, first);
+    let other = enqueue(echo_signature("b"), Unique::by_signature())
+        .await
+        .unwrap();
+    assert_ne!(other, first);
+    assert_eq!(manager.list_tasks().await.unwrap().len(), 2);
+
+    // Duplicates by key can be rejected instead
+    let unique = Unique::by_key("webhook-1")
+        .with_window(Duration::from_millis(200))
+        .with_on_duplicate(OnDuplicate::Reject);
+    let first = enqueue(echo_signature("a"), unique.clone()).await.unwrap();
+    let rejected = enqueue(echo_signature("b"), unique.clone()).await;
+    assert!(matches!(rejected, Err(TaskError::Duplicate(id)) if id == first));
+
+    // The key is free again once its window has passed
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let again = enqueue(echo_signature("a"), unique).await.unwrap();
+    assert_ne!(again, first);
+}
+
+#[tokio::test]
+async fn test_unique_task_rejected_on_enqueue() {
+    let manager = TaskManager::builder(1)
+        .with_enqueue_middleware(TenantMiddleware)
+        .build();
+    let options = EnqueueOptions::new().with_unique(Unique::by_key("webhook-1"));
+
+    // A task that was never enqueued does not keep its key
+    let rejected = manager
+        .enqueue_task_with_options(
+            TaskSignature::new("echo".to_string(), vec![], HashMap::new()),
+            options.clone(),
+        )
+        .await;
+    assert!(matches!(rejected, Err(TaskError::ValidationError(_))));
+    let id = manager
+        .enqueue_task_with_options(echo_signature("a"), options)
+        .await
+        .unwrap();
+    assert!(manager.get_task(id).await.unwrap().is_some());
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "opentelemetry")]
+
+use bg_coor::core::{EnqueueOptions, TaskError, TaskSignature};
+use bg_coor::task_manager::TaskManager;
+use bg_coor::worker::registry::TaskHandler;
+use opentelemetry::trace::{TraceContextExt, TracerProvider};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::collections::HashMap;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Returns the trace id of the span it runs in.
+struct TraceIdHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for TraceIdHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        let context = tracing::Span::current().context();
+        let trace_id = context.span().span_context().trace_id();
+        Ok(trace_id.to_string().into_bytes())
+    }
+}
+
+#[tokio::test]
+async fn test_trace_context_propagation() {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let provider = SdkTracerProvider::builder().build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("trace", TraceIdHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let request = tracing::info_span!("http_request");
+    let trace_id = request.context().span().span_context().trace_id();
+    let signature = TaskSignature::new("trace".to_string(), vec![], HashMap::new());
+    let handle = manager
+        .enqueue_task_with_handle(signature, EnqueueOptions::new())
+        .instrument(request)
+        .await
+        .unwrap();
+
+    let task = manager.get_task(handle.id()).await.unwrap().unwrap();
+    let traceparent = task.headers()["traceparent"].as_str().unwrap();
+    assert!(traceparent.contains(&trace_id.to_string()));
+
+    let result = handle.result().await.unwrap();
+    assert_eq!(String::from_utf8(result).unwrap(), trace_id.to_string());
+
+    manager.shutdown().await.unwrap();
+}
@@ -2,9 +2,10 @@ use bg_coor::broker::memory::MemoryBroker;
 use bg_coor::broker::traits::Broker;
 use bg_coor::core::{Task, TaskError, TaskSignature, TaskStatus};
 use bg_coor::storage::{MemoryStorage, Storage};
-use bg_coor::worker::{executor::*, pool::*, registry::*};
+use bg_coor::worker::{executor::*, pool::*, reaper::*, registry::*};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 struct TestHandler;
 
@@ -19,6 +20,80 @@ impl TaskHandler for TestHandler {
     }
 }
 
+/// Asks for an immediate retry on the first attempt.
+struct RetryOnceHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for RetryOnceHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        unreachable!()
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &bg_coor::worker::context::TaskContext,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        if ctx.retries() == 0 {
+            return Err(TaskError::Retry {
+                after: Some(Duration::ZERO),
+            });
+        }
+        Ok(vec![])
+    }
+}
+
+/// Reports progress, then runs until told to stop.
+struct BusyHandler {
+    stop: Arc<tokio::sync::Notify>,
+}
+
+#[async_trait::async_trait]
+impl TaskHandler for BusyHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        unreachable!()
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &bg_coor::worker::context::TaskContext,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        ctx.progress().update(1, 2);
+        self.stop.notified().await;
+        Ok(vec![])
+    }
+}
+
+/// Counts its runs, each taking `duration`.
+struct SlowHandler {
+    runs: Arc<std::sync::atomic::AtomicUsize>,
+    duration: Duration,
+}
+
+#[async_trait::async_trait]
+impl TaskHandler for SlowHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        self.runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        tokio::time::sleep(self.duration).await;
+        Ok(vec![])
+    }
+}
+
 #[tokio::test]
 async fn test_task_registry() {
     let registry = TaskRegistry::new();
@@ -40,12 +115,122 @@ async fn test_executor() {
     registry.register("test_task", TestHandler).unwrap();
 
     let executor = Executor::new(broker, storage, registry);
-    let task = Task::new("test_task".to_string(), vec![], 3);
+    let payload = TaskSignature::new("test_task".to_string(), vec![], HashMap::new());
+    let task = Task::new("test_task".to_string(), payload.to_bytes(), 3);
 
     let result = executor.execute_task(task).await;
     assert!(result.is_ok());
 }
 
+#[tokio::test]
+async fn test_executor_releases_reservation() {
+    let broker = Arc::new(MemoryBroker::new().with_visibility_timeout(Duration::from_millis(50)));
+    let storage = Arc::new(MemoryStorage::new());
+    let registry = Arc::new(TaskRegistry::new());
+    registry.register("retry_once", RetryOnceHandler).unwrap();
+    let executor = Executor::new(broker.clone(), storage.clone(), registry);
+
+    let payload = TaskSignature::new("retry_once".to_string(), vec![], HashMap::new());
+    let task = Task::new("retry_once".to_string(), payload.to_bytes(), 3);
+    storage.store_task(&task).await.unwrap();
+    broker.push(&task).await.unwrap();
+
+    // The retry is pushed again and can be reserved by another worker at
+    // once; that reservation is its own and expires on its own.
+    let reserved = broker.pop().await.unwrap().unwrap();
+    executor.execute_task(reserved).await.unwrap();
+    let retried = broker.pop().await.unwrap().unwrap();
+    assert_eq!(retried.id(), task.id());
+    assert!(broker.pop().await.unwrap().is_none());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let redelivered = broker.pop().await.unwrap().unwrap();
+    assert_eq!(redelivered.id(), task.id());
+
+    // A finished task is not redelivered
+    executor.execute_task(redelivered).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(broker.pop().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_heartbeat_extends_reservation() {
+    let broker = Arc::new(MemoryBroker::new().with_visibility_timeout(Duration::from_millis(100)));
+    let storage = Arc::new(MemoryStorage::new());
+    let registry = Arc::new(TaskRegistry::new());
+    let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let handler = SlowHandler {
+        runs: runs.clone(),
+        duration: Duration::from_millis(500),
+    };
+    registry.register("slow", handler).unwrap();
+
+    let payload = TaskSignature::new("slow".to_string(), vec![], HashMap::new());
+    let task = Task::new("slow".to_string(), payload.to_bytes(), 3);
+    storage.store_task(&task).await.unwrap();
+    broker.push(&task).await.unwrap();
+
+    // A second worker keeps polling while the first runs past the timeout
+    let reserved = broker.pop().await.unwrap().unwrap();
+    let executor = Executor::new(broker.clone(), storage.clone(), registry.clone())
+        .with_heartbeat_interval(Duration::from_millis(20));
+    let running = tokio::spawn(async move { executor.execute_task(reserved).await });
+    let other = Executor::new(broker.clone(), storage.clone(), registry);
+    while !running.is_finished() {
+        if let Some(task) = broker.pop().await.unwrap() {
+            other.execute_task(task).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    running.await.unwrap().unwrap();
+
+    assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+    let stored = storage.load_task(task.id()).await.unwrap().unwrap();
+    assert_eq!(stored.status(), &TaskStatus::Completed);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(broker.pop().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_heartbeat_keeps_concurrent_changes() {
+    let broker = Arc::new(MemoryBroker::new());
+    let storage = Arc::new(MemoryStorage::new());
+    let registry = Arc::new(TaskRegistry::new());
+    let stop = Arc::new(tokio::sync::Notify::new());
+    registry
+        .register("busy", BusyHandler { stop: stop.clone() })
+        .unwrap();
+    let executor = Executor::new(broker, storage.clone(), registry)
+        .with_heartbeat_interval(Duration::from_millis(10))
+        .with_progress_interval(Duration::from_millis(10));
+
+    let payload = TaskSignature::new("busy".to_string(), vec![], HashMap::new());
+    let task = Task::new("busy".to_string(), payload.to_bytes(), 3);
+    storage.store_task(&task).await.unwrap();
+    let running = tokio::spawn(async move { executor.execute_task(task).await });
+
+    // Changed by someone else while the task runs
+    let id = loop {
+        let tasks = storage.list_tasks().await.unwrap();
+        if tasks[0].is_running() {
+            break tasks[0].id();
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    };
+    let mut changed = storage.load_task(id).await.unwrap().unwrap();
+    changed.set_header("tenant", "acme");
+    storage.update_task(&changed).await.unwrap();
+    let first_beat = changed.heartbeat_at().unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let stored = storage.load_task(id).await.unwrap().unwrap();
+    assert_eq!(stored.headers()["tenant"], "acme");
+    assert!(stored.heartbeat_at().unwrap() > first_beat);
+    assert_eq!(stored.progress().unwrap().current(), Some(1));
+
+    stop.notify_one();
+    running.await.unwrap().unwrap();
+}
+
 #[tokio::test]
 async fn test_worker_pool() {
     let broker = Arc::new(MemoryBroker::new());
@@ -67,3 +252,93 @@ async fn test_worker_pool() {
     let task_status = storage.load_task(task.id()).await.unwrap().unwrap();
     assert_eq!(task_status.status(), &TaskStatus::Completed);
 }
+
+#[tokio::test]
+async fn test_executor_missing_handler_fails_task() {
+    let broker = Arc::new(MemoryBroker::new());
+    let storage = Arc::new(MemoryStorage::new());
+    let registry = Arc::new(TaskRegistry::new());
+
+    let executor = Executor::new(broker, storage.clone(), registry);
+    let task = Task::new("unknown_task".to_string(), vec![], 3);
+
+    let result = executor.execute_task(task.clone()).await;
+    assert!(matches!(result, Err(TaskError::HandlerNotFound(_))));
+
+    let stored = storage.load_task(task.id()).await.unwrap().unwrap();
+    assert!(matches!(stored.status(), TaskStatus::Failed(_)));
+}
+
+#[tokio::test]
+async fn test_reaper_recovers_abandoned_tasks() {
+    let broker = Arc::new(MemoryBroker::new());
+    let storage = Arc::new(MemoryStorage::new());
+    let config = ReaperConfig {
+        interval: Duration::from_secs(1),
+        lease_timeout: Duration::from_millis(10),
+    };
+    let reaper = Reaper::new(broker.clone(), storage.clone(), config);
+
+    // Both were reserved by a worker that died while running them
+    let mut retryable = Task::new("test_task".to_string(), vec![], 1);
+    let mut exhausted = Task::new("test_task".to_string(), vec![], 0);
+    for task in [&mut retryable, &mut exhausted] {
+        broker.push(task).await.unwrap();
+        broker.pop().await.unwrap().unwrap();
+        task.set_status(TaskStatus::Running);
+        task.heartbeat();
+        storage.store_task(task).await.unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(reaper.reap().await.unwrap(), 2);
+
+    // Tasks with retries left go back to the broker
+    let requeued = storage.load_task(retryable.id()).await.unwrap().unwrap();
+    assert_eq!(requeued.status(), &TaskStatus::Pending);
+    assert_eq!(requeued.retries(), 1);
+    let popped = broker.pop().await.unwrap().unwrap();
+    assert_eq!(popped.id(), retryable.id());
+
+    // Tasks out of retries are failed with a reason
+    let failed = storage.load_task(exhausted.id()).await.unwrap().unwrap();
+    match failed.status() {
+        TaskStatus::Failed(reason) => assert!(reason.contains("Worker lost")),
+        status => panic!("Expected Failed status, got {:?}", status),
+    }
+    assert!(broker.pop().await.unwrap().is_none());
+
+    // Nothing is left to reap
+    assert_eq!(reaper.reap().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_reaper_leaves_redelivered_tasks_alone() {
+    let broker = Arc::new(MemoryBroker::new().with_visibility_timeout(Duration::from_millis(50)));
+    let storage = Arc::new(MemoryStorage::new());
+    let config = ReaperConfig {
+        interval: Duration::from_secs(1),
+        lease_timeout: Duration::from_millis(10),
+    };
+    let reaper = Reaper::new(broker.clone(), storage.clone(), config);
+
+    let mut task = Task::new("test_task".to_string(), vec![], 3);
+    broker.push(&task).await.unwrap();
+    broker.pop().await.unwrap().unwrap();
+    task.set_status(TaskStatus::Running);
+    task.heartbeat();
+    storage.store_task(&task).await.unwrap();
+
+    // The reservation expires and the broker puts the task back first
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut other = Task::new("test_task".to_string(), vec![], 3);
+    other.set_priority(9);
+    broker.push(&other).await.unwrap();
+    assert_eq!(broker.pop().await.unwrap().unwrap().id(), other.id());
+
+    assert_eq!(reaper.reap().await.unwrap(), 1);
+    let requeued = broker.pop().await.unwrap().unwrap();
+    assert_eq!(requeued.id(), task.id());
+    assert_eq!(requeued.retries(), 1);
+    assert!(broker.pop().await.unwrap().is_none());
+}
@@ -0,0 +1,590 @@
+use bg_coor::core::{
+    Chain, Chord, EnqueueOptions, Group, GroupStatus, NodeState, Saga, SagaStatus, TaskError,
+    TaskSignature, TaskStatus, Workflow, WorkflowStatus,
+};
+use bg_coor::task_manager::TaskManager;
+use bg_coor::worker::context::TaskContext;
+use bg_coor::worker::registry::TaskHandler;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Adds up its numeric arguments, including those inside arrays.
+struct SumHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for SumHandler {
+    async fn handle(
+        &self,
+        args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        fn sum(value: &serde_json::Value) -> i64 {
+            match value {
+                serde_json::Value::Array(values) => values.iter().map(sum).sum(),
+                value => value.as_i64().unwrap_or(0),
+            }
+        }
+        Ok(serde_json::to_vec(&args.iter().map(sum).sum::<i64>())?)
+    }
+}
+
+struct FailHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for FailHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        Err(TaskError::Fail("boom".to_string()))
+    }
+}
+
+/// Runs until cancelled.
+struct WaitHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for WaitHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        unreachable!()
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &TaskContext,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        ctx.cancelled().await;
+        Err(TaskError::Cancelled(ctx.id()))
+    }
+}
+
+/// Fans out one child per argument, nested arrays being scanned in turn,
+/// and adds up the children's results once they have all finished.
+struct ScanHandler;
+
+#[async_trait::async_trait]
+impl TaskHandler for ScanHandler {
+    async fn handle(
+        &self,
+        _args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        unreachable!()
+    }
+
+    async fn handle_with_context(
+        &self,
+        ctx: &TaskContext,
+        args: Vec<serde_json::Value>,
+        _kwargs: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<u8>, TaskError> {
+        let children = ctx.children().await?;
+        if children.is_empty() {
+            for arg in args {
+                let signature = match arg {
+                    serde_json::Value::Array(args) => {
+                        TaskSignature::new("scan".to_string(), args, HashMap::new())
+                    }
+                    arg => TaskSignature::new("sum".to_string(), vec![arg], HashMap::new()),
+                };
+                ctx.enqueue(signature, EnqueueOptions::new()).await?;
+            }
+            return Err(TaskError::Suspend);
+        }
+        let total: i64 = children
+            .iter()
+            .map(|child| child.result_value().as_i64().unwrap())
+            .sum();
+        Ok(serde_json::to_vec(&total)?)
+    }
+}
+
+fn sum(args: &[i64]) -> TaskSignature {
+    TaskSignature::new(
+        "sum".to_string(),
+        args.iter().map(|arg| serde_json::json!(arg)).collect(),
+        HashMap::new(),
+    )
+}
+
+fn fail() -> TaskSignature {
+    TaskSignature::new("fail".to_string(), vec![], HashMap::new())
+}
+
+async fn start_manager() -> TaskManager {
+    let mut manager = TaskManager::builder(2).build();
+    manager.register_handler("sum", SumHandler).unwrap();
+    manager.register_handler("fail", FailHandler).unwrap();
+    manager.register_handler("wait", WaitHandler).unwrap();
+    manager.start().await.unwrap();
+    manager
+}
+
+async fn finished_status(manager: &TaskManager, id: uuid::Uuid) -> WorkflowStatus {
+    for _ in 0..500 {
+        let status = manager.workflow_status(id).await.unwrap();
+        if status != WorkflowStatus::Running {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Workflow {} did not finish", id);
+}
+
+async fn finished_saga_status(manager: &TaskManager, id: uuid::Uuid) -> SagaStatus {
+    for _ in 0..500 {
+        let status = manager.saga_status(id).await.unwrap();
+        if status.is_finished() {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Saga {} did not finish", id);
+}
+
+async fn result(manager: &TaskManager, id: uuid::Uuid) -> i64 {
+    let task = manager
+        .task_handle(id)
+        .wait_timeout(Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(task.status(), &TaskStatus::Completed);
+    serde_json::from_slice(task.get_result().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_chain() {
+    let mut manager = start_manager().await;
+
+    let chain = Chain::new([sum(&[1, 2]), sum(&[10])]).then(sum(&[100]));
+    let ids = manager
+        .enqueue_chain(chain, EnqueueOptions::new().with_header("tenant", "acme"))
+        .await
+        .unwrap();
+    assert_eq!(ids.len(), 3);
+    assert_eq!(result(&manager, ids[2]).await, 113);
+    let last = manager.get_task(ids[2]).await.unwrap().unwrap();
+    assert_eq!(last.headers()["tenant"], "acme");
+
+    // The chain stops at the first task that does not complete
+    let ids = manager
+        .enqueue_chain(
+            Chain::new([sum(&[1]), fail(), sum(&[2])]),
+            EnqueueOptions::new(),
+        )
+        .await
+        .unwrap();
+    let failed = manager
+        .task_handle(ids[1])
+        .wait_timeout(Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert!(matches!(failed.status(), TaskStatus::Failed(_)));
+    let skipped = manager.get_task(ids[2]).await.unwrap().unwrap();
+    let reason = format!("Chain step {} did not complete", ids[1]);
+    assert_eq!(skipped.status(), &TaskStatus::Failed(reason));
+
+    assert!(matches!(
+        manager
+            .enqueue_chain(Chain::default(), EnqueueOptions::new())
+            .await,
+        Err(TaskError::InvalidArgument(_))
+    ));
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_chain_step_fails() {
+    let mut manager = start_manager().await;
+
+    // Waiting on the last step resolves even though it never runs
+    let ids = manager
+        .enqueue_chain(
+            Chain::new([fail(), sum(&[1]), sum(&[2])]),
+            EnqueueOptions::new(),
+        )
+        .await
+        .unwrap();
+    let last = manager
+        .task_handle(ids[2])
+        .wait_timeout(Duration::from_secs(10))
+        .await
+        .unwrap();
+    let reason = format!("Chain step {} did not complete", ids[0]);
+    assert_eq!(last.status(), &TaskStatus::Failed(reason));
+    assert!(last.get_result().is_none());
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_group() {
+    let mut manager = start_manager().await;
+
+    let group = Group::new([sum(&[1]), sum(&[2])]).with(sum(&[3]));
+    let record = manager
+        .enqueue_group(group, EnqueueOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(record.members().len(), 3);
+    for (id, expected) in record.members().iter().zip([1, 2, 3]) {
+        assert_eq!(result(&manager, *id).await, expected);
+        let task = manager.get_task(*id).await.unwrap().unwrap();
+        assert_eq!(task.group_id(), Some(record.id()));
+    }
+
+    let stored = manager.get_group(record.id()).await.unwrap().unwrap();
+    assert_eq!(stored.members(), record.members());
+    assert!(stored.callback().is_none());
+
+    // An empty group has nothing to wait for
+    let record = manager
+        .enqueue_group(Group::default(), EnqueueOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(record.status(), &GroupStatus::Completed);
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_chord() {
+    let mut manager = start_manager().await;
+
+    let group = Group::new([sum(&[1]), sum(&[2]), sum(&[3])]);
+    let record = manager
+        .enqueue_chord(Chord::new(group, sum(&[10])), EnqueueOptions::new())
+        .await
+        .unwrap();
+    let callback = record.callback().unwrap().id();
+    assert_eq!(result(&manager, callback).await, 16);
+    let stored = manager.get_group(record.id()).await.unwrap().unwrap();
+    assert_eq!(stored.status(), &GroupStatus::Completed);
+
+    // An empty group runs its callback right away
+    let record = manager
+        .enqueue_chord(
+            Chord::new(Group::default(), sum(&[5])),
+            EnqueueOptions::new(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result(&manager, record.callback().unwrap().id()).await, 5);
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_chord_member_fails() {
+    let mut manager = start_manager().await;
+
+    // The callback fails without running if a member does not complete
+    let group = Group::new([sum(&[1]), fail()]);
+    let record = manager
+        .enqueue_chord(Chord::new(group, sum(&[])), EnqueueOptions::new())
+        .await
+        .unwrap();
+    let callback = manager
+        .task_handle(record.callback().unwrap().id())
+        .wait_timeout(Duration::from_secs(10))
+        .await
+        .unwrap();
+    let reason = format!("Group member {} did not complete", record.members()[1]);
+    assert_eq!(callback.status(), &TaskStatus::Failed(reason.clone()));
+    assert!(callback.result_value().is_null());
+
+    let stored = manager.get_group(record.id()).await.unwrap().unwrap();
+    assert_eq!(stored.status(), &GroupStatus::Failed(reason));
+
+    manager.shutdown().await.unwrap();
+}
+
+#[test]
+fn test_workflow_validation() {
+    assert!(Workflow::new().validate().is_err());
+
+    let unknown = Workflow::new()
+        .with_node("a", sum(&[1]))
+        .on_success("a", "b");
+    assert!(matches!(
+        unknown.validate(),
+        Err(TaskError::ValidationError(_))
+    ));
+
+    let cycle = Workflow::new()
+        .with_node("a", sum(&[1]))
+        .with_node("b", sum(&[2]))
+        .with_node("c", sum(&[3]))
+        .on_success("a", "b")
+        .on_success("b", "c")
+        .always("c", "b");
+    assert!(matches!(
+        cycle.validate(),
+        Err(TaskError::ValidationError(_))
+    ));
+
+    let diamond = Workflow::new()
+        .with_node("a", sum(&[1]))
+        .with_node("b", sum(&[2]))
+        .with_node("c", sum(&[3]))
+        .with_node("d", sum(&[4]))
+        .on_success("a", "b")
+        .on_success("a", "c")
+        .on_success("b", "d")
+        .on_success("c", "d");
+    assert!(diamond.validate().is_ok());
+}
+
+#[tokio::test]
+async fn test_workflow() {
+    let mut manager = start_manager().await;
+
+    // c runs after a and b succeed; d only if c fails, e only if it succeeds
+    let workflow = Workflow::new()
+        .with_node("a", sum(&[1]))
+        .with_node("b", sum(&[2]))
+        .with_node("c", fail())
+        .with_node("d", sum(&[4]))
+        .with_node("e", sum(&[5]))
+        .with_node("f", sum(&[6]))
+        .on_success("a", "c")
+        .on_success("b", "c")
+        .on_failure("c", "d")
+        .on_success("c", "e")
+        .always("e", "f");
+    let record = manager
+        .enqueue_workflow(workflow, EnqueueOptions::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        finished_status(&manager, record.id()).await,
+        WorkflowStatus::Completed
+    );
+    let workflow = manager.get_workflow(record.id()).await.unwrap().unwrap();
+    let state = |key: &str| workflow.node(key).unwrap().state();
+    assert_eq!(state("a"), NodeState::Completed);
+    assert_eq!(state("b"), NodeState::Completed);
+    assert_eq!(state("c"), NodeState::Failed);
+    assert_eq!(state("d"), NodeState::Completed);
+    assert_eq!(state("e"), NodeState::Skipped);
+    assert_eq!(state("f"), NodeState::Skipped);
+
+    let d = manager
+        .get_task(workflow.node("d").unwrap().task_id())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(d.workflow_id(), Some(record.id()));
+    assert_eq!(d.workflow_node(), Some("d"));
+    assert!(manager
+        .get_task(workflow.node("e").unwrap().task_id())
+        .await
+        .unwrap()
+        .is_none());
+
+    // A failure nothing handles fails the workflow
+    let workflow = Workflow::new()
+        .with_node("a", fail())
+        .with_node("b", sum(&[1]))
+        .on_success("a", "b");
+    let record = manager
+        .enqueue_workflow(workflow, EnqueueOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        finished_status(&manager, record.id()).await,
+        WorkflowStatus::Failed
+    );
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cancel_workflow() {
+    let mut manager = start_manager().await;
+
+    let workflow = Workflow::new()
+        .with_node(
+            "a",
+            TaskSignature::new("wait".to_string(), vec![], HashMap::new()),
+        )
+        .with_node("b", sum(&[1]))
+        .on_success("a", "b");
+    let record = manager
+        .enqueue_workflow(workflow, EnqueueOptions::new())
+        .await
+        .unwrap();
+    let a = record.node("a").unwrap().task_id();
+    while manager.get_task(a).await.unwrap().unwrap().status() != &TaskStatus::Running {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert!(manager.cancel_workflow(record.id()).await.unwrap());
+    let task = manager
+        .task_handle(a)
+        .wait_timeout(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(task.status(), &TaskStatus::Cancelled);
+
+    let workflow = manager.get_workflow(record.id()).await.unwrap().unwrap();
+    assert_eq!(workflow.status(), WorkflowStatus::Cancelled);
+    assert_eq!(workflow.node("b").unwrap().state(), NodeState::Cancelled);
+    assert!(!manager.cancel_workflow(record.id()).await.unwrap());
+    assert!(matches!(
+        manager.cancel_workflow(uuid::Uuid::new_v4()).await,
+        Err(TaskError::NotFound(_))
+    ));
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_child_tasks() {
+    // A single worker: suspended parents must not hold it while their
+    // children run.
+    let mut manager = TaskManager::builder(1).build();
+    manager.register_handler("sum", SumHandler).unwrap();
+    manager.register_handler("scan", ScanHandler).unwrap();
+    manager.start().await.unwrap();
+
+    let signature = TaskSignature::new(
+        "scan".to_string(),
+        vec![
+            serde_json::json!(1),
+            serde_json::json!([2, 3]),
+            serde_json::json!(4),
+        ],
+        HashMap::new(),
+    );
+    let id = manager.enqueue_task(signature, 0).await.unwrap();
+    assert_eq!(result(&manager, id).await, 10);
+
+    let tree = manager.task_tree(id).await.unwrap().unwrap();
+    assert_eq!(tree.task().id(), id);
+    assert_eq!(tree.task().parent_id(), None);
+    assert_eq!(tree.children().len(), 3);
+    for child in tree.children() {
+        assert_eq!(child.task().parent_id(), Some(id));
+        assert_eq!(child.task().root_id(), Some(id));
+    }
+    let nested = &tree.children()[1];
+    assert_eq!(nested.task().name(), "scan");
+    assert_eq!(nested.children().len(), 2);
+    for grandchild in nested.children() {
+        assert_eq!(grandchild.task().parent_id(), Some(nested.task().id()));
+        assert_eq!(grandchild.task().root_id(), Some(id));
+        assert!(grandchild.children().is_empty());
+    }
+    assert_eq!(manager.get_children(id).await.unwrap().len(), 3);
+    assert!(manager
+        .task_tree(uuid::Uuid::new_v4())
+        .await
+        .unwrap()
+        .is_none());
+
+    manager.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_saga() {
+    let mut manager = start_manager().await;
+
+    let saga = Saga::new()
+        .step(sum(&[1]), sum(&[-1]))
+        .step(sum(&[2]), sum(&[-2]));
+    let record = manager
+        .enqueue_saga(saga, EnqueueOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        finished_saga_status(&manager, record.id()).await,
+        SagaStatus::Completed
+    );
+    assert_eq!(result(&manager, record.steps()[1].action().id()).await, 2);
+    for step in record.steps() {
+        let compensation = step.compensation().unwrap().id();
+        assert!(manager.get_task(compensation).await.unwrap().is_none());
+    }
+
+    // The third step fails: the second has nothing to undo, so only the
+    // first is compensated; the failed step itself is not.
+    let saga = Saga::new()
+        .step(sum(&[1]), sum(&[-1]))
+        .step_without_compensation(sum(&[2]))
+        .step(sum(&[3]), sum(&[-3]))
+        .step(fail(), sum(&[-4]));
+    let record = manager
+        .enqueue_saga(saga, EnqueueOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        finished_saga_status(&manager, record.id()).await,
+        SagaStatus::Compensated
+    );
+    let steps = record.steps();
+    let third = manager
+        .get_task(steps[2].compensation().unwrap().id())
+        .await
+        .unwrap()
+        .unwrap();
+    let first = manager
+        .get_task(steps[0].compensation().unwrap().id())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(third.status(), &TaskStatus::Completed);
+    assert_eq!(first.status(), &TaskStatus::Completed);
+    assert!(third.created_at() < first.created_at());
+    let failed = steps[3].compensation().unwrap().id();
+    assert!(manager.get_task(failed).await.unwrap().is_none());
+
+    let saga = manager.get_saga(record.id()).await.unwrap().unwrap();
+    let transitions: Vec<_> = saga
+        .transitions()
+        .iter()
+        .map(|transition| (transition.status(), transition.step()))
+        .collect();
+    assert_eq!(
+        transitions,
+        vec![
+            (SagaStatus::Running, 0),
+            (SagaStatus::Compensating, 3),
+            (SagaStatus::Compensated, 0),
+        ]
+    );
+
+    // A compensation that fails stops the rollback
+    let saga = Saga::new()
+        .step(sum(&[1]), sum(&[-1]))
+        .step(sum(&[2]), fail())
+        .step(fail(), sum(&[-3]));
+    let record = manager
+        .enqueue_saga(saga, EnqueueOptions::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        finished_saga_status(&manager, record.id()).await,
+        SagaStatus::CompensationFailed
+    );
+    let first = record.steps()[0].compensation().unwrap().id();
+    assert!(manager.get_task(first).await.unwrap().is_none());
+
+    assert!(matches!(
+        manager
+            .enqueue_saga(Saga::new(), EnqueueOptions::new())
+            .await,
+        Err(TaskError::InvalidArgument(_))
+    ));
+
+    manager.shutdown().await.unwrap();
+}